@@ -0,0 +1,390 @@
+use anyhow::{bail, Context, Result};
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::sync::{Arc, RwLock};
+use tokio::time::sleep;
+use tracing::{error, info, warn};
+
+use crate::config::parse_duration;
+use crate::redact;
+
+/// Webhook notified when a feed stops producing new data.
+#[derive(Deserialize, Serialize, Clone)]
+pub struct AlertingConfig {
+    /// May reference `${ENV}` or `${file:...}` like scraper values
+    pub webhook_url: String,
+    pub format: Option<WebhookFormat>,
+    /// How often thresholds are checked, "1m" when absent
+    pub check_interval: Option<String>,
+}
+
+/// Debug output without the webhook URL, which embeds its credentials
+impl fmt::Debug for AlertingConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AlertingConfig")
+            .field("webhook_url", &redact::REDACTED)
+            .field("format", &self.format)
+            .field("check_interval", &self.check_interval)
+            .finish()
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum WebhookFormat {
+    #[default]
+    Generic,
+    Slack,
+    Teams,
+}
+
+/// Per-scraper alert thresholds. Scrapers without one are not alerted on.
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct FeedAlertConfig {
+    /// Max time since `Storage::save_if_new` last stored anything new
+    pub no_new_data_after: Option<String>,
+    /// Max time the newest `delivery_to` seen may lie in the past
+    pub max_data_age: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct FeedThresholds {
+    no_new_data_after: Option<Duration>,
+    max_data_age: Option<Duration>,
+}
+
+impl FeedThresholds {
+    pub fn from_config(config: &FeedAlertConfig) -> Result<Self> {
+        let parse = |value: &Option<String>, key: &str| -> Result<Option<Duration>> {
+            value.as_deref()
+                .map(|v| {
+                    let threshold = parse_duration(v).with_context(|| format!("Invalid alert.{}", key))?;
+                    if threshold <= Duration::zero() {
+                        bail!("alert.{} must be positive", key);
+                    }
+                    Ok(threshold)
+                })
+                .transpose()
+        };
+        Ok(Self {
+            no_new_data_after: parse(&config.no_new_data_after, "no_new_data_after")?,
+            max_data_age: parse(&config.max_data_age, "max_data_age")?,
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum AlertKind {
+    NoNewData,
+    DataTooOld,
+}
+
+struct FeedState {
+    thresholds: FeedThresholds,
+    last_new_data: DateTime<Utc>,
+    newest_delivery_to: Option<DateTime<Utc>>,
+    /// Alerts whose firing notification was delivered, with its message
+    firing: BTreeMap<AlertKind, String>,
+}
+
+/// A threshold that started or stopped being exceeded.
+pub struct AlertEvent {
+    kind: AlertKind,
+    pub scraper: String,
+    pub firing: bool,
+    pub message: String,
+    pub last_new_data: DateTime<Utc>,
+    pub newest_delivery_to: Option<DateTime<Utc>>,
+}
+
+/// Per-scraper record of when new data last arrived, fed by the scraper workers.
+#[derive(Default)]
+pub struct FeedTracker {
+    feeds: RwLock<BTreeMap<String, FeedState>>,
+}
+
+impl FeedTracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Start tracking a scraper. The startup time counts as the last new data.
+    pub fn register(&self, name: &str, thresholds: FeedThresholds) {
+        let mut feeds = self.feeds.write().unwrap_or_else(|e| e.into_inner());
+        feeds.insert(name.to_string(), FeedState {
+            thresholds,
+            last_new_data: Utc::now(),
+            newest_delivery_to: None,
+            firing: BTreeMap::new(),
+        });
+    }
+
+    /// Record a successful scrape whose newest row ends at `newest_delivery_to`.
+    pub fn record_scrape(&self, name: &str, newest_delivery_to: DateTime<Utc>) {
+        let mut feeds = self.feeds.write().unwrap_or_else(|e| e.into_inner());
+        if let Some(feed) = feeds.get_mut(name) {
+            if feed.newest_delivery_to.is_none_or(|newest| newest_delivery_to > newest) {
+                feed.newest_delivery_to = Some(newest_delivery_to);
+            }
+        }
+    }
+
+    /// Record that `Storage::save_if_new` stored new data.
+    pub fn record_new_data(&self, name: &str) {
+        let mut feeds = self.feeds.write().unwrap_or_else(|e| e.into_inner());
+        if let Some(feed) = feeds.get_mut(name) {
+            feed.last_new_data = Utc::now();
+        }
+    }
+
+    /// Compare every feed with its thresholds and return the alerts whose state differs from
+    /// the last one delivered. Nothing changes until an event is passed to `delivered`, so
+    /// events that could not be sent are returned again by the next call.
+    pub fn evaluate(&self, now: DateTime<Utc>) -> Vec<AlertEvent> {
+        let feeds = self.feeds.read().unwrap_or_else(|e| e.into_inner());
+        let mut events = Vec::new();
+
+        for (name, feed) in feeds.iter() {
+            let mut exceeded = Vec::new();
+
+            if let Some(threshold) = feed.thresholds.no_new_data_after {
+                let silent_for = now - feed.last_new_data;
+                if silent_for > threshold {
+                    exceeded.push((AlertKind::NoNewData, format!(
+                        "no new data saved for {} (threshold {})", format_duration(silent_for), format_duration(threshold)
+                    )));
+                }
+            }
+
+            if let (Some(threshold), Some(newest)) = (feed.thresholds.max_data_age, feed.newest_delivery_to) {
+                let age = now - newest;
+                if age > threshold {
+                    exceeded.push((AlertKind::DataTooOld, format!(
+                        "newest delivery period ended {} ago at {} (threshold {})",
+                        format_duration(age), newest, format_duration(threshold)
+                    )));
+                }
+            }
+
+            for (kind, message) in &exceeded {
+                if !feed.firing.contains_key(kind) {
+                    events.push(AlertEvent {
+                        kind: *kind,
+                        scraper: name.clone(),
+                        firing: true,
+                        message: message.clone(),
+                        last_new_data: feed.last_new_data,
+                        newest_delivery_to: feed.newest_delivery_to,
+                    });
+                }
+            }
+
+            let recovered = feed.firing.iter()
+                .filter(|(kind, _)| !exceeded.iter().any(|(k, _)| k == *kind));
+            for (kind, message) in recovered {
+                events.push(AlertEvent {
+                    kind: *kind,
+                    scraper: name.clone(),
+                    firing: false,
+                    message: format!("recovered, was: {}", message),
+                    last_new_data: feed.last_new_data,
+                    newest_delivery_to: feed.newest_delivery_to,
+                });
+            }
+        }
+
+        events
+    }
+
+    /// Record that `event` was sent, so it is not returned by `evaluate` again.
+    pub fn delivered(&self, event: &AlertEvent) {
+        let mut feeds = self.feeds.write().unwrap_or_else(|e| e.into_inner());
+        if let Some(feed) = feeds.get_mut(&event.scraper) {
+            if event.firing {
+                feed.firing.insert(event.kind, event.message.clone());
+            } else {
+                feed.firing.remove(&event.kind);
+            }
+        }
+    }
+}
+
+/// Periodically checks the tracker and posts state changes to the webhook.
+pub struct Alerter {
+    client: reqwest::Client,
+    webhook_url: String,
+    format: WebhookFormat,
+    check_interval: std::time::Duration,
+    tracker: Arc<FeedTracker>,
+}
+
+impl Alerter {
+    pub fn new(config: &AlertingConfig, tracker: Arc<FeedTracker>) -> Result<Self> {
+        let check_interval = parse_duration(config.check_interval.as_deref().unwrap_or("1m"))
+            .context("Invalid alerting.check_interval")?;
+        if check_interval <= Duration::zero() {
+            bail!("alerting.check_interval must be positive");
+        }
+        let check_interval = check_interval.to_std()?;
+
+        Ok(Self {
+            client: reqwest::Client::builder()
+                .timeout(std::time::Duration::from_secs(30))
+                .build()?,
+            webhook_url: config.webhook_url.clone(),
+            format: config.format.unwrap_or_default(),
+            check_interval,
+            tracker,
+        })
+    }
+
+    pub async fn run(&self) {
+        info!("Starting staleness alerting, checking every {}s", self.check_interval.as_secs());
+
+        loop {
+            sleep(self.check_interval).await;
+
+            for event in self.tracker.evaluate(Utc::now()) {
+                if event.firing {
+                    warn!("Feed {} is stale: {}", event.scraper, event.message);
+                } else {
+                    info!("Feed {} {}", event.scraper, event.message);
+                }
+
+                match self.send(&event).await {
+                    Ok(()) => self.tracker.delivered(&event),
+                    Err(e) => error!("Failed to send alert for {}, retrying at the next check: {:?}", event.scraper, e),
+                }
+            }
+        }
+    }
+
+    async fn send(&self, event: &AlertEvent) -> Result<()> {
+        self.client
+            .post(&self.webhook_url)
+            .json(&payload(self.format, event))
+            .send()
+            .await?
+            .error_for_status()?;
+        Ok(())
+    }
+}
+
+fn payload(format: WebhookFormat, event: &AlertEvent) -> Value {
+    let status = if event.firing { "stale" } else { "recovered" };
+    let title = if event.firing {
+        format!("⚠ Feed {} is stale", event.scraper)
+    } else {
+        format!("✓ Feed {} recovered", event.scraper)
+    };
+
+    match format {
+        WebhookFormat::Generic => json!({
+            "scraper": event.scraper,
+            "status": status,
+            "message": event.message,
+            "last_new_data": event.last_new_data,
+            "newest_delivery_to": event.newest_delivery_to,
+            "timestamp": Utc::now(),
+        }),
+        WebhookFormat::Slack => json!({
+            "text": format!("{}: {}", title, event.message),
+        }),
+        WebhookFormat::Teams => json!({
+            "@type": "MessageCard",
+            "@context": "https://schema.org/extensions",
+            "summary": title,
+            "themeColor": if event.firing { "D70000" } else { "2EB886" },
+            "title": title,
+            "text": event.message,
+        }),
+    }
+}
+
+fn format_duration(duration: Duration) -> String {
+    let minutes = duration.num_minutes();
+    if minutes >= 60 {
+        format!("{}h {}m", minutes / 60, minutes % 60)
+    } else {
+        format!("{}m", minutes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tracker() -> FeedTracker {
+        let tracker = FeedTracker::new();
+        let thresholds = FeedThresholds::from_config(&FeedAlertConfig {
+            no_new_data_after: Some("2h".to_string()),
+            max_data_age: None,
+        }).unwrap();
+        tracker.register("feed", thresholds);
+        tracker
+    }
+
+    #[test]
+    fn debug_hides_the_webhook_url() {
+        let config = AlertingConfig {
+            webhook_url: "https://hooks.slack.com/services/T000/B000/secret".to_string(),
+            format: Some(WebhookFormat::Slack),
+            check_interval: None,
+        };
+        let debug = format!("{:?}", config);
+        assert!(!debug.contains("secret"));
+        assert!(debug.contains("Slack"));
+    }
+
+    #[test]
+    fn thresholds_and_interval_must_be_positive() {
+        let thresholds = |no_new_data_after: &str| FeedThresholds::from_config(&FeedAlertConfig {
+            no_new_data_after: Some(no_new_data_after.to_string()),
+            max_data_age: None,
+        });
+        assert!(thresholds("30m").is_ok());
+        assert!(thresholds("0s").is_err());
+        assert!(thresholds("-1h").is_err());
+
+        let alerter = |check_interval: &str| Alerter::new(&AlertingConfig {
+            webhook_url: "http://localhost/hook".to_string(),
+            format: None,
+            check_interval: Some(check_interval.to_string()),
+        }, Arc::new(FeedTracker::new()));
+        assert!(alerter("30s").is_ok());
+        assert!(alerter("0s").is_err());
+        assert!(alerter("-1m").is_err());
+    }
+
+    #[test]
+    fn undelivered_events_are_returned_again() {
+        let tracker = tracker();
+        let later = Utc::now() + Duration::hours(3);
+
+        let events = tracker.evaluate(later);
+        assert_eq!(events.len(), 1);
+        assert!(events[0].firing);
+        // The send failed, so the alert is still pending
+        assert_eq!(tracker.evaluate(later).len(), 1);
+
+        tracker.delivered(&events[0]);
+        assert!(tracker.evaluate(later).is_empty());
+
+        tracker.record_new_data("feed");
+        let events = tracker.evaluate(Utc::now());
+        assert_eq!(events.len(), 1);
+        assert!(!events[0].firing);
+        tracker.delivered(&events[0]);
+        assert!(tracker.evaluate(Utc::now()).is_empty());
+    }
+
+    #[test]
+    fn no_recovery_without_a_delivered_alert() {
+        let tracker = tracker();
+        assert_eq!(tracker.evaluate(Utc::now() + Duration::hours(3)).len(), 1);
+        assert!(tracker.evaluate(Utc::now()).is_empty());
+    }
+}
@@ -1,15 +1,214 @@
 use anyhow::{Context, Result};
 use chrono::{NaiveDate, Duration};
-use std::env;
+use futures::stream::{self, StreamExt};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
+use tokio::sync::Mutex;
 use tracing::{info, error};
 use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt, Layer};
 use indicatif::{ProgressBar, ProgressStyle};
+use ve_energy_scrapers::models::scraper_data::ScraperData;
+use ve_energy_scrapers::scraper::Scraper;
 
-use scraping_service::{config, storage, scraper_factory, uploader};
-use config::load_config;
-use storage::Storage;
-use uploader::Uploader;
+use scraping_service::{cli, config, storage, scraper_factory, uploader};
+use scraping_service::checkpoint::{Checkpoint, DEFAULT_STATE_DIR};
+use scraping_service::rate_limit::{self, HostRateLimiter};
+use scraping_service::redact::{self, RedactingMakeWriter};
+use scraping_service::report::{self, OutputFormat, EXIT_GAPS, EXIT_OK, EXIT_UPLOAD_FAILED};
+use scraping_service::window::{self, ChunkSize, ScrapeWindow};
+use config::{load_config, ScraperConfig};
+use storage::{PartitionDiff, PartitionState, SaveMode, Storage};
+use uploader::{UploadReport, Uploader};
+
+const COMMAND: cli::Command = cli::Command {
+    name: "backfill",
+    about: "Scrapes historical days for one or more scrapers and stores them like the service does.\n\
+            <scrapers> is a scraper name from the config, 'all', a comma-separated list or a glob like 'apg_*'.",
+    usage: "<scrapers> (--from <date> [--to <date>] | <start_date> <end_date>) [OPTIONS]",
+    options: &[
+        cli::FROM,
+        cli::TO,
+        cli::OptionSpec {
+            name: "mode",
+            value: Some("fill|overwrite|compare"),
+            help: "fill (default) only adds missing rows and values, overwrite replaces stored values, \
+                   compare prints per-day differences without writing anything",
+        },
+        cli::OptionSpec { name: "concurrency", value: Some("N"), help: "Days scraped at once across all scrapers (default 4)" },
+        cli::OptionSpec {
+            name: "only-missing",
+            value: None,
+            help: "Only scrape days whose partition is missing or doesn't cover the whole day",
+        },
+        cli::OptionSpec { name: "check-s3", value: None, help: "With --only-missing, also treat days missing in S3 as missing" },
+        cli::OptionSpec { name: "resume", value: None, help: "Skip days completed by an earlier run and retry the failed ones" },
+        cli::OptionSpec { name: "state-dir", value: Some("DIR"), help: "Where checkpoints are kept (default state)" },
+        cli::FORMAT,
+        cli::CONFIG,
+        cli::DATA_DIR,
+    ],
+    examples: &[
+        "apg_at_cz_exchange 2025-01-01 2025-01-31",
+        "'apg_*,ENTSOEImb15MinAT' --from 2025-01-01 --to 2025-12-31 --concurrency 8",
+        "all --from -7d --to yesterday --only-missing",
+    ],
+};
+
+/// Days scraped at once across all selected scrapers, unless `--concurrency=N` is given
+const DEFAULT_CONCURRENCY: usize = 4;
+
+/// Days fetched per request for scrapers without a `backfill_chunk`
+const DEFAULT_CHUNK: ChunkSize = ChunkSize::Days(1);
+
+/// Longest run of consecutive missing days fetched with one request by `--only-missing`,
+/// for scrapers without a `backfill_chunk`
+const DEFAULT_MISSING_CHUNK: ChunkSize = ChunkSize::Days(7);
+
+/// What the backfill does with the scraped rows, from `--mode=...`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum BackfillMode {
+    Save(SaveMode),
+    /// Report what an overwrite would change without writing anything
+    Compare,
+}
+
+impl BackfillMode {
+    fn parse(value: &str) -> Result<Self> {
+        match value {
+            "fill" => Ok(Self::Save(SaveMode::Fill)),
+            "overwrite" => Ok(Self::Save(SaveMode::Overwrite)),
+            "compare" => Ok(Self::Compare),
+            _ => anyhow::bail!("Unknown mode '{}', expected fill, overwrite or compare", value),
+        }
+    }
+}
+
+/// Outcome of backfilling one day.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+enum DayStatus {
+    /// New or changed rows were saved
+    Saved,
+    /// Everything returned was already stored
+    Unchanged,
+    /// With `--mode=compare`, the scraped rows differ from the stored ones
+    Differs,
+    /// The source returned no rows
+    Empty,
+    /// Completed by an earlier run, skipped with `--resume`
+    Checkpointed,
+    /// Already stored for the whole day (and in S3 with `--check-s3`), skipped with `--only-missing`
+    Present,
+    /// Stored for the whole day but missing in S3, queued for upload instead of scraped
+    Queued,
+    ScrapeFailed,
+    SaveFailed,
+    CompareFailed,
+}
+
+impl DayStatus {
+    fn is_failure(self) -> bool {
+        matches!(self, DayStatus::ScrapeFailed | DayStatus::SaveFailed | DayStatus::CompareFailed)
+    }
+}
+
+/// One row of the report, flat so it can be written as CSV.
+#[derive(Serialize)]
+struct DayResult {
+    scraper: String,
+    date: NaiveDate,
+    status: DayStatus,
+    rows: usize,
+    error: Option<String>,
+    /// Only set with `--mode=compare`
+    rows_added: Option<usize>,
+    values_changed: Option<usize>,
+    max_abs_delta: Option<f64>,
+}
+
+impl DayResult {
+    fn new(scraper: &str, date: NaiveDate, status: DayStatus, rows: usize, error: Option<String>) -> Self {
+        Self {
+            scraper: scraper.to_string(),
+            date,
+            status,
+            rows,
+            error: error.map(|e| redact::scrub(&e)),
+            rows_added: None,
+            values_changed: None,
+            max_abs_delta: None,
+        }
+    }
+
+    fn with_diff(mut self, diff: PartitionDiff) -> Self {
+        self.rows_added = Some(diff.rows_added);
+        self.values_changed = Some(diff.values_changed);
+        self.max_abs_delta = Some(diff.max_abs_delta);
+        self
+    }
+}
+
+#[derive(Serialize)]
+struct ScraperReport {
+    scraper: String,
+    start_date: NaiveDate,
+    end_date: NaiveDate,
+    rows_saved: usize,
+    failed_days: usize,
+    days: Vec<DayResult>,
+}
+
+#[derive(Serialize)]
+struct FileError {
+    file: String,
+    error: String,
+}
+
+#[derive(Serialize)]
+struct UploadSummary {
+    uploaded: usize,
+    failed: Vec<FileError>,
+    dead_lettered: Vec<FileError>,
+}
+
+impl From<&UploadReport> for UploadSummary {
+    fn from(report: &UploadReport) -> Self {
+        let file_errors = |files: &[(String, String)]| files.iter()
+            .map(|(file, e)| FileError { file: file.clone(), error: redact::scrub(e) })
+            .collect();
+        Self {
+            uploaded: report.uploaded.len(),
+            failed: file_errors(&report.failed),
+            dead_lettered: file_errors(&report.dead_lettered),
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct BackfillReport {
+    scrapers: Vec<ScraperReport>,
+    /// Absent when no S3 bucket is configured
+    upload: Option<UploadSummary>,
+}
+
+/// A selected scraper with everything its days need.
+struct ScraperJob<'a> {
+    config: &'a ScraperConfig,
+    scraper: Box<dyn Scraper>,
+    window: ScrapeWindow,
+    chunk: ChunkSize,
+    /// Rate limit key, from the scraper's `url`
+    host: Option<String>,
+    checkpoint: Mutex<Checkpoint>,
+}
+
+impl ScraperJob<'_> {
+    fn name(&self) -> &str {
+        &self.config.scraper_config.name
+    }
+}
 
 #[tokio::main]
 async fn main() -> Result<()> {
@@ -17,85 +216,137 @@ async fn main() -> Result<()> {
     #[cfg(debug_assertions)]
     dotenvy::dotenv().ok();
 
+    let args = COMMAND.parse_env();
+    let format = OutputFormat::from_args(&args)?;
+    let resume = args.flag("resume");
+    let only_missing = args.flag("only-missing");
+    let check_s3 = args.flag("check-s3");
+    let mode = args.value("mode").map_or(Ok(BackfillMode::Save(SaveMode::Fill)), BackfillMode::parse)?;
+    let concurrency = match args.parse_value::<usize>("concurrency")? {
+        Some(value) => Some(value).filter(|n| *n > 0).context("--concurrency must be a positive number")?,
+        None => DEFAULT_CONCURRENCY,
+    };
+    let state_dir = PathBuf::from(args.value("state-dir").unwrap_or(DEFAULT_STATE_DIR));
+
     tracing_subscriber::registry()
         .with(
             tracing_subscriber::fmt::layer()
+                .with_writer(RedactingMakeWriter::new(format.log_writer()))
                 .with_filter(tracing_subscriber::EnvFilter::try_from_default_env()
                     .unwrap_or_else(|_| tracing_subscriber::EnvFilter::new("info")))
         )
         .init();
 
-    let args: Vec<String> = env::args().collect();
-    
-    if args.len() < 4 {
-        eprintln!("Usage: {} <scraper_name> <start_date> <end_date>", args[0]);
-        eprintln!("  scraper_name: Name of the scraper from config.json");
-        eprintln!("  start_date: Start date in YYYY-MM-DD format");
-        eprintln!("  end_date: End date in YYYY-MM-DD format");
-        eprintln!("\nExample: {} apg_at_cz_exchange 2025-01-01 2025-01-31", args[0]);
-        std::process::exit(1);
-    }
-
-    let scraper_name = &args[1];
-    let start_date_str = &args[2];
-    let end_date_str = &args[3];
-
-    // Parse dates
-    let start_date = NaiveDate::parse_from_str(start_date_str, "%Y-%m-%d")
-        .context("Failed to parse start_date. Use YYYY-MM-DD format")?;
-    
-    let end_date = NaiveDate::parse_from_str(end_date_str, "%Y-%m-%d")
-        .context("Failed to parse end_date. Use YYYY-MM-DD format")?;
+    let Some(scraper_selector) = args.positional.first() else {
+        COMMAND.exit_with_usage("missing <scrapers>");
+    };
+    let (start_date, end_date) = args.date_range(1).unwrap_or_else(|e| COMMAND.exit_with_usage(e));
+    if check_s3 && !only_missing {
+        anyhow::bail!("--check-s3 only applies together with --only-missing");
+    }
+    if check_s3 && mode == BackfillMode::Compare {
+        anyhow::bail!("--check-s3 queues uploads and can't be combined with --mode=compare");
+    }
 
     // Calculate total days
     let total_days = (end_date - start_date).num_days() + 1;
-    
-    if total_days <= 0 {
-        eprintln!("Error: end_date must be equal to or after start_date");
-        std::process::exit(1);
-    }
-
-    info!("Starting backfill for {} from {} to {} ({} days)", 
-        scraper_name, start_date, end_date, total_days);
 
     // Load config
-    let config = load_config("config.json").context("Failed to load config.json")?;
+    let config_path = args.config_path();
+    let config = load_config(config_path).with_context(|| format!("Failed to load {}", config_path))?;
     
-    // Find the scraper config
-    let scraper_config = config.scrapers.iter()
-        .find(|s| s.scraper_config.name == *scraper_name)
-        .context(format!("Scraper '{}' not found in config.json", scraper_name))?;
+    let selected = config.select_scrapers(scraper_selector)?;
+    info!("Starting backfill for {} scraper(s) from {} to {} ({} days, {} at a time)", 
+        selected.len(), start_date, end_date, total_days, concurrency);
 
-    // Set up uploader if S3 is configured
+    // Set up uploader if S3 is configured; files are uploaded by a flush at the end
     let mut dirty_files_handle = None;
-    let mut uploader_handle = None;
+    let mut uploader = None;
     
-    if let Some(bucket) = config.get_s3_bucket() {
+    if mode == BackfillMode::Compare {
+        info!("Comparing only, nothing is written or uploaded");
+    } else if let Some(bucket) = config.get_s3_bucket() {
         info!("S3 bucket configured: {}, setting up uploader", bucket);
-        let uploader = Uploader::new(
+        let s3_uploader = Uploader::new(
             bucket,
             config.get_s3_region(),
             config.get_s3_endpoint(),
             config.get_s3_prefix(),
+            args.data_dir(),
+            config.upload_settings()?,
         ).await?;
-        dirty_files_handle = Some(uploader.get_pending_files_handle());
-        
-        let handle = tokio::spawn(async move {
-            uploader.run().await;
-        });
-        uploader_handle = Some(handle);
+        dirty_files_handle = Some(s3_uploader.get_pending_files_handle());
+        uploader = Some(s3_uploader);
     } else {
+        if check_s3 {
+            anyhow::bail!("--check-s3 needs an S3 bucket in config.json or S3_BUCKET");
+        }
         info!("No S3 bucket configured, data will only be stored locally");
     }
 
     // Create storage with uploader support
-    let storage = Arc::new(Storage::new("data", dirty_files_handle));
+    let storage = Arc::new(Storage::new(args.data_dir(), dirty_files_handle));
+    let limiter = HostRateLimiter::new(&config.rate_limits.clone().unwrap_or_default());
+
+    // Create scrapers
+    let mut jobs = Vec::with_capacity(selected.len());
+    for scraper_config in selected {
+        let name = &scraper_config.scraper_config.name;
+        let scraper = scraper_factory::create_scraper(scraper_config)?;
+        let window = scraper_config.scrape_window()
+            .context(format!("Invalid scrape window for '{}'", name))?;
+        let chunk = scraper_config.backfill_chunk()
+            .context(format!("Invalid backfill_chunk for '{}'", name))?
+            .unwrap_or(if only_missing { DEFAULT_MISSING_CHUNK } else { DEFAULT_CHUNK });
+        let host = rate_limit::scraper_host(scraper_config);
+        if let Some(per_minute) = host.as_deref().and_then(|h| limiter.limit_for(h)) {
+            info!("{}: limited to {} requests per minute to {}", name, per_minute, host.as_deref().unwrap_or_default());
+        }
+
+        // Without --resume the run starts over and replaces the previous checkpoint
+        let checkpoint = if resume {
+            let checkpoint = Checkpoint::load(&state_dir, name)?;
+            info!("Resuming {}: {} days completed, {} failed in the previous run",
+                name, checkpoint.completed.len(), checkpoint.failed.len());
+            checkpoint
+        } else {
+            Checkpoint::new(&state_dir, name)
+        };
 
-    // Create scraper
-    let scraper = scraper_factory::create_scraper(&scraper_config.scraper_config)?;
+        jobs.push(ScraperJob { config: scraper_config, scraper, window, chunk, host, checkpoint: Mutex::new(checkpoint) });
+    }
+
+    // Days settled without scraping, and runs of days to scrape per scraper
+    let mut days: Vec<DayResult> = Vec::new();
+    let mut tasks: Vec<(&ScraperJob, NaiveDate, NaiveDate)> = Vec::new();
+    for job in &jobs {
+        let mut todo = Vec::new();
+        for date in (0..total_days).map(|offset| start_date + Duration::days(offset)) {
+            if job.checkpoint.lock().await.is_completed(date) {
+                days.push(DayResult::new(job.name(), date, DayStatus::Checkpointed, 0, None));
+            } else {
+                todo.push(date);
+            }
+        }
+
+        if only_missing {
+            let s3 = uploader.as_ref().filter(|_| check_s3);
+            todo = find_missing_days(job, todo, &storage, s3, &mut days).await?;
+        }
+        let runs = job.chunk.split(&todo);
+        info!("{}: {} days to scrape in {} requests of up to {}", job.name(), todo.len(), runs.len(), job.chunk);
+        tasks.extend(runs.into_iter().map(|(first, last)| (job, first, last)));
+    }
+    // Date by date across scrapers, so scrapers on different hosts run side by side
+    tasks.sort_by_key(|(_, first, _)| *first);
     
     // Create progress bar with known length
-    let pb = ProgressBar::new(total_days as u64);
+    let task_days: i64 = tasks.iter().map(|(_, first, last)| (*last - *first).num_days() + 1).sum();
+    let pb = if format.is_text() {
+        ProgressBar::new(task_days as u64)
+    } else {
+        ProgressBar::hidden()
+    };
     pb.set_style(
         ProgressStyle::default_bar()
             .template("{spinner:.green} [{elapsed_precise}] [{bar:40.cyan/blue}] {pos}/{len} days ({eta})\n{msg}")
@@ -103,69 +354,263 @@ async fn main() -> Result<()> {
             .progress_chars("#>-")
     );
     
-    let mut total_records = 0;
-    let mut days_with_data = 0;
-    let mut current_date = start_date;
+    let scraped: Vec<Vec<DayResult>> = stream::iter(tasks)
+        .map(|(job, first, last)| backfill_days(job, first, last, mode, &storage, &limiter, &pb))
+        .buffer_unordered(concurrency)
+        .collect()
+        .await;
+    days.extend(scraped.into_iter().flatten());
+    days.sort_by(|a, b| (&a.scraper, a.date).cmp(&(&b.scraper, b.date)));
     
-    // Process each day
-    for _ in 0..total_days {
-        // Use same approach as main service: query a window around the target date
-        // This ensures we get all data for the day even with timezone variations
-        let target_datetime = current_date.and_hms_opt(12, 0, 0)
-            .context("Invalid time")?
-            .and_utc();
-        let day_start = target_datetime - Duration::days(1); // Day before
-        let day_end = target_datetime + Duration::days(1);   // Day after
-        
-        pb.set_message(format!("Processing {}", current_date));
-        
-        // Perform the scrape for this day
-        match scraper.scrape_data(day_start, day_end).await {
-            Ok(data) => {
-                if !data.is_empty() {
-                    info!("Scraped {} records for {}", data.len(), current_date);
-                    match storage.save_backfill(
-                        &scraper_config.scraper_config.name,
-                        scraper_config.sub_data_folder.as_deref(),
-                        &data
-                    ).await {
-                        Ok(saved) => {
-                            if saved {
-                                total_records += data.len();
-                                days_with_data += 1;
-                            } else {
-                                pb.println(format!("  {} - {} records (already exists)", current_date, data.len()));
-                            }
-                        }
-                        Err(e) => {
-                            pb.println(format!("⚠ Failed to save data for {}: {:?}", current_date, e));
-                            error!("Failed to save data for {}: {:?}", current_date, e);
-                        }
-                    }
-                } else {
-                    pb.println(format!("  {} - No data returned", current_date));
+    if mode == BackfillMode::Compare {
+        let differing = || days.iter().filter(|day| day.status == DayStatus::Differs);
+        pb.finish_with_message(format!("✓ Compared: {} days differ, {} rows added, {} values changed, max delta {}",
+            differing().count(),
+            differing().filter_map(|day| day.rows_added).sum::<usize>(),
+            differing().filter_map(|day| day.values_changed).sum::<usize>(),
+            differing().filter_map(|day| day.max_abs_delta).fold(0.0, f64::max)));
+    } else {
+        let saved_days = || days.iter().filter(|day| day.status == DayStatus::Saved);
+        pb.finish_with_message(format!("✓ Completed: {} records from {} days with data", 
+            saved_days().map(|day| day.rows).sum::<usize>(), saved_days().count()));
+    }
+
+    let failed_days = days.iter().filter(|day| day.status.is_failure()).count();
+    if failed_days > 0 && format.is_text() && mode != BackfillMode::Compare {
+        println!("⚠ {} days failed, rerun with --resume to retry only those", failed_days);
+    }
+
+    // Upload everything written by this backfill (and anything left over from earlier runs)
+    let mut upload = None;
+    if let Some(uploader) = uploader {
+        info!("Uploading backfilled files to S3...");
+        let upload_report = uploader.flush().await;
+        if format.is_text() {
+            print_upload_report(&upload_report, &uploader.dead_letter_path());
+        } else {
+            for (file_path, e) in upload_report.failed.iter().chain(&upload_report.dead_lettered) {
+                error!("Failed to upload {}: {}", file_path, e);
+            }
+        }
+        upload = Some(UploadSummary::from(&upload_report));
+    }
+
+    let upload_failed = upload.as_ref().is_some_and(|u| !u.failed.is_empty() || !u.dead_lettered.is_empty());
+    let mut scrapers = Vec::with_capacity(jobs.len());
+    for job in &jobs {
+        let (scraper_days, rest): (Vec<DayResult>, Vec<DayResult>) = days.into_iter()
+            .partition(|day| day.scraper == job.name());
+        days = rest;
+        scrapers.push(ScraperReport {
+            scraper: job.name().to_string(),
+            start_date,
+            end_date,
+            rows_saved: scraper_days.iter().filter(|day| day.status == DayStatus::Saved).map(|day| day.rows).sum(),
+            failed_days: scraper_days.iter().filter(|day| day.status.is_failure()).count(),
+            days: scraper_days,
+        });
+    }
+    let report = BackfillReport { scrapers, upload };
+
+    match format {
+        OutputFormat::Text => {}
+        OutputFormat::Json => report::print_json(&report)?,
+        OutputFormat::Csv => report::print_csv(report.scrapers.iter().flat_map(|s| &s.days))?,
+    }
+
+    let exit_code = if failed_days > 0 {
+        EXIT_GAPS
+    } else if upload_failed {
+        EXIT_UPLOAD_FAILED
+    } else {
+        EXIT_OK
+    };
+    std::process::exit(exit_code);
+}
+
+/// With `--only-missing`, the days of `dates` whose partition is missing or incomplete.
+/// The others are added to `results`; with `s3`, days complete locally but missing in S3 are
+/// queued for upload, and days missing locally but present in S3 are not scraped again.
+async fn find_missing_days(
+    job: &ScraperJob<'_>,
+    dates: Vec<NaiveDate>,
+    storage: &Storage,
+    s3: Option<&Uploader>,
+    results: &mut Vec<DayResult>,
+) -> Result<Vec<NaiveDate>> {
+    let subfolder = job.config.sub_data_folder.as_deref();
+    let files: Vec<String> = dates.iter()
+        .map(|date| storage.partition_path(job.name(), subfolder, *date))
+        .collect();
+    let not_in_s3 = match s3 {
+        Some(uploader) => Some(uploader.missing_in_s3(&files).await
+            .with_context(|| format!("Failed to list S3 objects of {}", job.name()))?),
+        None => None,
+    };
+
+    let mut missing = Vec::new();
+    for (date, file_path) in dates.into_iter().zip(files) {
+        let in_s3 = not_in_s3.as_ref().map(|not_in_s3| !not_in_s3.contains(&file_path));
+        match (storage.partition_state(job.name(), subfolder, date).await?, in_s3) {
+            (PartitionState::Complete, Some(false)) => {
+                if let Some(uploader) = s3 {
+                    uploader.get_pending_files_handle().insert(file_path).await?;
                 }
+                results.push(DayResult::new(job.name(), date, DayStatus::Queued, 0, None));
             }
-            Err(e) => {
-                pb.println(format!("⚠ Failed to scrape {}: {:?}", current_date, e));
-                error!("Failed to scrape {}: {:?}", current_date, e);
+            (PartitionState::Complete, _) | (PartitionState::Missing, Some(true)) => {
+                results.push(DayResult::new(job.name(), date, DayStatus::Present, 0, None));
             }
+            _ => missing.push(date),
         }
-        
-        pb.inc(1);
-        current_date = current_date + Duration::days(1);
     }
-    
-    pb.finish_with_message(format!("✓ Completed: {} records from {} days with data", 
-        total_records, days_with_data));
+    Ok(missing)
+}
+
+/// Scrape the days `first..=last` of one scraper with a single request, save (or compare)
+/// each day, and record the outcomes in its checkpoint.
+async fn backfill_days(
+    job: &ScraperJob<'_>,
+    first: NaiveDate,
+    last: NaiveDate,
+    mode: BackfillMode,
+    storage: &Storage,
+    limiter: &HostRateLimiter,
+    pb: &ProgressBar,
+) -> Vec<DayResult> {
+    let name = job.name();
+    let subfolder = job.config.sub_data_folder.as_deref();
+    let dates: Vec<NaiveDate> = first.iter_days().take_while(|date| *date <= last).collect();
+    let label = if first == last { first.to_string() } else { format!("{} to {}", first, last) };
 
-    // Wait for uploader to process remaining files
-    if uploader_handle.is_some() {
-        info!("Waiting for S3 uploads to complete...");
-        info!("The uploader processes files every 60 seconds.");
-        // Wait at least 90 seconds to ensure one full upload cycle completes
-        tokio::time::sleep(tokio::time::Duration::from_secs(90)).await;
+    // Exactly these local days, so each delivery period is requested once
+    let (range_start, range_end) = job.window.range_for_days(first, last);
+
+    if let Some(host) = &job.host {
+        limiter.acquire(host).await;
     }
+    pb.set_message(format!("Processing {} {}", name, label));
+
+    // Perform the scrape for these days
+    let mut results = Vec::with_capacity(dates.len());
+    match job.scraper.scrape_data(range_start, range_end).await {
+        Ok(data) => {
+            if !data.is_empty() {
+                info!("Scraped {} records for {} {}", data.len(), name, label);
+            }
 
-    Ok(())
+            // Rows by the local day whose partition they are stored in
+            let mut by_day: BTreeMap<NaiveDate, Vec<ScraperData>> = BTreeMap::new();
+            for item in data {
+                let date = window::local_date(item.delivery_from, &chrono_tz::Europe::Vienna);
+                by_day.entry(date).or_default().push(item);
+            }
+
+            for &date in &dates {
+                let Some(rows) = by_day.remove(&date) else {
+                    pb.println(format!("  {} {} - No data returned", name, date));
+                    results.push(DayResult::new(name, date, DayStatus::Empty, 0, None));
+                    continue;
+                };
+                let save_mode = match mode {
+                    BackfillMode::Save(save_mode) => save_mode,
+                    BackfillMode::Compare => {
+                        results.push(compare_day(job, date, &rows, storage, pb).await);
+                        continue;
+                    }
+                };
+                let result = match storage.save_backfill(name, subfolder, &rows, save_mode).await {
+                    Ok(true) => DayResult::new(name, date, DayStatus::Saved, rows.len(), None),
+                    Ok(false) => {
+                        pb.println(format!("  {} {} - {} records (already exists)", name, date, rows.len()));
+                        DayResult::new(name, date, DayStatus::Unchanged, rows.len(), None)
+                    }
+                    Err(e) => {
+                        pb.println(redact::scrub(&format!("⚠ Failed to save data for {} {}: {:?}", name, date, e)));
+                        error!("Failed to save data for {} {}: {:?}", name, date, e);
+                        DayResult::new(name, date, DayStatus::SaveFailed, rows.len(), Some(format!("{:#}", e)))
+                    }
+                };
+                results.push(result);
+            }
+
+            // Rows of neighbouring days, returned when the window has an offset
+            let overlap: Vec<ScraperData> = by_day.into_values().flatten().collect();
+            if let (false, BackfillMode::Save(save_mode)) = (overlap.is_empty(), mode) {
+                if let Err(e) = storage.save_backfill(name, subfolder, &overlap, save_mode).await {
+                    pb.println(redact::scrub(&format!("⚠ Failed to save data around {} {}: {:?}", name, label, e)));
+                    error!("Failed to save data around {} {}: {:?}", name, label, e);
+                    for result in results.iter_mut().filter(|result| !result.status.is_failure()) {
+                        result.status = DayStatus::SaveFailed;
+                        result.error = Some(redact::scrub(&format!("{:#}", e)));
+                    }
+                }
+            }
+        }
+        Err(e) => {
+            pb.println(redact::scrub(&format!("⚠ Failed to scrape {} {}: {:?}", name, label, e)));
+            error!("Failed to scrape {} {}: {:?}", name, label, e);
+            let error = format!("{:#}", e);
+            results.extend(dates.iter().map(|date| DayResult::new(name, *date, DayStatus::ScrapeFailed, 0, Some(error.clone()))));
+        }
+    }
+
+    // A comparison changes nothing, so the checkpoint stays as it is
+    if mode == BackfillMode::Compare {
+        pb.inc(dates.len() as u64);
+        return results;
+    }
+
+    // One write per chunk; the lock keeps concurrent chunks of a scraper from writing out of order
+    let mut checkpoint = job.checkpoint.lock().await;
+    for result in &results {
+        match &result.error {
+            Some(e) => checkpoint.mark_failed(result.date, e),
+            None => checkpoint.mark_completed(result.date),
+        }
+    }
+    if let Err(e) = checkpoint.save().await {
+        error!("Failed to update checkpoint for {}: {:?}", name, e);
+    }
+    drop(checkpoint);
+
+    pb.inc(dates.len() as u64);
+    results
+}
+
+/// Compare the scraped rows of one day with the stored ones.
+async fn compare_day(job: &ScraperJob<'_>, date: NaiveDate, rows: &[ScraperData], storage: &Storage, pb: &ProgressBar) -> DayResult {
+    let name = job.name();
+    match storage.compare(name, job.config.sub_data_folder.as_deref(), rows).await {
+        Ok(diff) if diff.rows_added == 0 && diff.values_changed == 0 => {
+            DayResult::new(name, date, DayStatus::Unchanged, rows.len(), None).with_diff(diff)
+        }
+        Ok(diff) => {
+            pb.println(format!("  {} {} - {} rows added, {} values changed, max delta {}",
+                name, date, diff.rows_added, diff.values_changed, diff.max_abs_delta));
+            DayResult::new(name, date, DayStatus::Differs, rows.len(), None).with_diff(diff)
+        }
+        Err(e) => {
+            pb.println(redact::scrub(&format!("⚠ Failed to compare {} {}: {:?}", name, date, e)));
+            error!("Failed to compare {} {}: {:?}", name, date, e);
+            DayResult::new(name, date, DayStatus::CompareFailed, rows.len(), Some(format!("{:#}", e)))
+        }
+    }
+}
+
+fn print_upload_report(report: &UploadReport, dead_letter_path: &Path) {
+    println!("✓ Uploaded {} files to S3", report.uploaded.len());
+
+    if !report.failed.is_empty() {
+        println!("⚠ Failed to upload {} files, they stay queued for the next run:", report.failed.len());
+        for (file_path, e) in &report.failed {
+            println!("  - {}: {}", file_path, redact::scrub(e));
+        }
+    }
+    if !report.dead_lettered.is_empty() {
+        println!("✗ Gave up on {} files, see {:?}:", report.dead_lettered.len(), dead_letter_path);
+        for (file_path, e) in &report.dead_lettered {
+            println!("  - {}: {}", file_path, redact::scrub(e));
+        }
+    }
 }
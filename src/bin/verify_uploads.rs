@@ -1,15 +1,63 @@
 use anyhow::{Context, Result};
 use chrono::{NaiveDate, Duration, Datelike};
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
 use std::env;
-use tracing::info;
+use std::fmt;
+use std::path::Path;
+use std::time::UNIX_EPOCH;
+use tracing::{error, info, warn};
 use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt, Layer};
 use indicatif::{ProgressBar, ProgressStyle};
+use futures::stream::{self, StreamExt};
 
-use scraping_service::config;
+use scraping_service::{cli, config};
+use scraping_service::redact::{self, RedactingMakeWriter};
+use scraping_service::report::{self, OutputFormat, EXIT_ERROR, EXIT_GAPS, EXIT_OK};
+use scraping_service::uploader::{file_sha256, hex, SHA256_METADATA_KEY};
 use config::load_config;
 
 use aws_config;
 use aws_sdk_s3::Client;
+use aws_sdk_s3::error::DisplayErrorContext;
+
+const COMMAND: cli::Command = cli::Command {
+    name: "verify-uploads",
+    about: "Checks that every day of the selected scrapers was uploaded to S3.\n\
+            <scrapers> is a scraper name from the config, 'all', a comma-separated list or a glob like 'apg_*'.",
+    usage: "<scrapers> (--from <date> [--to <date>] | <start_date> <end_date>) [OPTIONS]",
+    options: &[
+        cli::FROM,
+        cli::TO,
+        cli::OptionSpec {
+            name: "checksum",
+            value: None,
+            help: "Compare the SHA-256 stored with each object against the local file",
+        },
+        cli::OptionSpec {
+            name: "compare-local",
+            value: None,
+            help: "Report each day as missing, stale in S3, only in S3 or in sync with the data directory",
+        },
+        cli::OptionSpec {
+            name: "head",
+            value: None,
+            help: "Check each day with a HEAD request instead of listing the scraper's prefix",
+        },
+        cli::OptionSpec { name: "concurrency", value: Some("N"), help: "HEAD requests in flight at once (default 8)" },
+        cli::FORMAT,
+        cli::CONFIG,
+        cli::DATA_DIR,
+    ],
+    examples: &[
+        "apg_imb_15min 2025-01-01 2026-01-05",
+        "all --from 2025-01-01 --to 2026-01-05 --checksum",
+        "all --from -7d --to yesterday --format json",
+    ],
+};
+
+/// HEAD requests in flight at once, unless `--concurrency=N` is given
+const DEFAULT_HEAD_CONCURRENCY: usize = 8;
 
 #[tokio::main]
 async fn main() -> Result<()> {
@@ -17,46 +65,36 @@ async fn main() -> Result<()> {
     #[cfg(debug_assertions)]
     dotenvy::dotenv().ok();
 
+    let args = COMMAND.parse_env();
+    let format = OutputFormat::from_args(&args)?;
+
     tracing_subscriber::registry()
         .with(
             tracing_subscriber::fmt::layer()
+                .with_writer(RedactingMakeWriter::new(format.log_writer()))
                 .with_filter(tracing_subscriber::EnvFilter::try_from_default_env()
                     .unwrap_or_else(|_| tracing_subscriber::EnvFilter::new("info")))
         )
         .init();
 
-    let args: Vec<String> = env::args().collect();
-    
-    if args.len() < 4 {
-        eprintln!("Usage: {} <scraper_name|all> <start_date> <end_date>", args[0]);
-        eprintln!("  scraper_name: Name of the scraper from config.json, or 'all' for all scrapers");
-        eprintln!("  start_date: Start date in YYYY-MM-DD format");
-        eprintln!("  end_date: End date in YYYY-MM-DD format");
-        eprintln!("\nExample: {} apg_imb_15min 2025-01-01 2026-01-05", args[0]);
-        eprintln!("Example: {} all 2025-01-01 2026-01-05", args[0]);
-        std::process::exit(1);
-    }
-
-    let scraper_filter = &args[1];
-    let start_date_str = &args[2];
-    let end_date_str = &args[3];
-
-    // Parse dates
-    let start_date = NaiveDate::parse_from_str(start_date_str, "%Y-%m-%d")
-        .context("Failed to parse start_date. Use YYYY-MM-DD format")?;
+    let verify_checksums = args.flag("checksum");
+    let compare_local = args.flag("compare-local");
+    let per_object = args.flag("head");
+    let concurrency = match args.parse_value::<usize>("concurrency")? {
+        Some(value) => Some(value).filter(|n| *n > 0).context("--concurrency must be a positive number")?,
+        None => DEFAULT_HEAD_CONCURRENCY,
+    };
     
-    let end_date = NaiveDate::parse_from_str(end_date_str, "%Y-%m-%d")
-        .context("Failed to parse end_date. Use YYYY-MM-DD format")?;
-
+    let Some(scraper_filter) = args.positional.first() else {
+        COMMAND.exit_with_usage("missing <scrapers>");
+    };
+    let (start_date, end_date) = args.date_range(1).unwrap_or_else(|e| COMMAND.exit_with_usage(e));
     let total_days = (end_date - start_date).num_days() + 1;
-    
-    if total_days <= 0 {
-        eprintln!("Error: end_date must be equal to or after start_date");
-        std::process::exit(1);
-    }
+    let data_dir = args.data_dir();
 
     // Load config
-    let config = load_config("config.json").context("Failed to load config.json")?;
+    let config_path = args.config_path();
+    let config = load_config(config_path).with_context(|| format!("Failed to load {}", config_path))?;
     
     let bucket = config.get_s3_bucket().context("No S3 bucket configured")?;
     let prefix = config.get_s3_prefix();
@@ -66,20 +104,15 @@ async fn main() -> Result<()> {
     info!("Verifying date range in S3 bucket: {}", bucket);
     info!("Date range: {} to {} ({} days)", start_date, end_date, total_days);
     
-    // Filter scrapers (clone to avoid move issues)
-    let scrapers_to_check: Vec<_> = if scraper_filter == "all" {
-        config.scrapers.clone()
-    } else {
-        config.scrapers.clone().into_iter()
-            .filter(|s| s.scraper_config.name == *scraper_filter)
-            .collect()
+    // `all`, a comma-separated list or globs, the same as backfill
+    let scrapers_to_check = match config.select_scrapers(scraper_filter) {
+        Ok(scrapers) => scrapers,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            std::process::exit(1);
+        }
     };
     
-    if scrapers_to_check.is_empty() {
-        eprintln!("Error: No matching scrapers found for '{}'", scraper_filter);
-        std::process::exit(1);
-    }
-    
     info!("Checking {} scraper(s)", scrapers_to_check.len());
     
     // Set up AWS S3 client with same credential logic as uploader
@@ -116,10 +149,17 @@ async fn main() -> Result<()> {
     }
     
     let client = Client::from_conf(s3_config_builder.build());
+    // Listings don't include user metadata, so stored checksums need a HEAD request each
+    let needs_checksums = verify_checksums || compare_local;
+    
+    let mut reports = Vec::new();
     
     // Check each scraper
-    for scraper_config in &scrapers_to_check {
-        println!("\n=== Checking {} ===", scraper_config.scraper_config.name);
+    for scraper_config in scrapers_to_check {
+        let name = scraper_config.scraper_config.name.clone();
+        if format.is_text() {
+            println!("\n=== Checking {} ===", name);
+        }
         
         // Construct the path the same way storage does
         let base_folder = if let Some(sub) = &scraper_config.sub_data_folder {
@@ -130,66 +170,410 @@ async fn main() -> Result<()> {
         
         // The S3 key is: prefix + base_folder + /year=.../month=.../day=.../data.parquet
         // This matches how the uploader constructs keys from local files
+        let partitions: Vec<Partition> = (0..total_days)
+            .map(|offset| Partition::new(start_date + Duration::days(offset), &prefix, data_dir, &base_folder))
+            .collect();
         
-        // Create progress bar
-        let pb = ProgressBar::new(total_days as u64);
-        pb.set_style(
-            ProgressStyle::default_bar()
-                .template("{spinner:.green} [{elapsed_precise}] [{bar:40.cyan/blue}] {pos}/{len} days\n{msg}")
-                .unwrap()
-                .progress_chars("#>-")
-        );
+        let (mut remote, mut lookup_errors) = if per_object {
+            let keys = partitions.iter().map(|p| p.s3_key.clone()).collect();
+            head_objects(&client, &bucket, keys, concurrency, true, format.is_text()).await
+        } else {
+            let dataset_prefix = format!("{}{}", prefix, base_folder);
+            match list_partitions(&client, &bucket, &dataset_prefix, start_date, end_date).await {
+                Ok(objects) => (objects, HashMap::new()),
+                Err(e) => {
+                    let message = redact::scrub(&format!("Failed to list {}: {:#}", dataset_prefix, e));
+                    error!("{}", message);
+                    if format.is_text() {
+                        println!("✗ {}", message);
+                    }
+                    reports.push(ScraperReport::failed(name, total_days, message));
+                    continue;
+                }
+            }
+        };
         
-        let mut missing_dates = Vec::new();
-        let mut current_date = start_date;
+        if needs_checksums && !per_object {
+            let keys = partitions.iter()
+                .filter(|p| remote.contains_key(&p.s3_key) && Path::new(&p.local_path).exists())
+                .map(|p| p.s3_key.clone())
+                .collect();
+            let (objects, errors) = head_objects(&client, &bucket, keys, concurrency, false, format.is_text()).await;
+            remote.extend(objects);
+            lookup_errors.extend(errors);
+        }
         
-        for _ in 0..total_days {
-            let year = current_date.year();
-            let month = current_date.month();
-            let day = current_date.day();
-            
-            // Construct S3 key: prefix + base_folder + partition path
-            let s3_key = format!("{}{}/year={}/month={:02}/day={:02}/data.parquet", 
-                prefix, base_folder, year, month, day);
-            
-            info!("Checking S3 key: {}", s3_key);
-            pb.set_message(format!("Checking {}", current_date));
+        let mut days = Vec::with_capacity(partitions.len());
+        for partition in &partitions {
+            let object = remote.get(&partition.s3_key);
+            let local_path = Path::new(&partition.local_path);
             
-            // Check if file exists in S3
-            match client
-                .head_object()
-                .bucket(&bucket)
-                .key(&s3_key)
-                .send()
-                .await
-            {
-                Ok(_) => {
-                    info!("Found: {}", s3_key);
-                    // File exists
+            let (status, detail) = if let Some(e) = lookup_errors.get(&partition.s3_key) {
+                (DayStatus::Error, Some(e.clone()))
+            } else if compare_local {
+                (compare_with_local(local_path, object)?, None)
+            } else {
+                match object {
+                    Some(object) if verify_checksums => match check_checksum(local_path, object.sha256.as_deref())? {
+                        ChecksumStatus::Match => (DayStatus::Present, None),
+                        ChecksumStatus::NoLocalFile => (DayStatus::Present, Some("no local file to compare".to_string())),
+                        ChecksumStatus::NotStored => (DayStatus::NoChecksum, None),
+                        ChecksumStatus::Mismatch => (DayStatus::ChecksumMismatch, None),
+                    },
+                    Some(_) => (DayStatus::Present, None),
+                    None => (DayStatus::Missing, None),
                 }
-                Err(e) => {
-                    info!("Not found: {} - Error: {:?}", s3_key, e);
-                    missing_dates.push(current_date);
-                    pb.println(format!("  ⚠ Missing: {}", current_date));
-                }
-            }
+            };
             
-            pb.inc(1);
-            current_date = current_date + Duration::days(1);
+            days.push(DayResult { scraper: name.clone(), date: partition.date, status, detail });
         }
         
-        pb.finish_and_clear();
-        
-        // Print summary for this scraper
-        if missing_dates.is_empty() {
-            println!("✓ All {} days present in S3", total_days);
-        } else {
-            println!("⚠ Missing {} of {} days:", missing_dates.len(), total_days);
-            for date in &missing_dates {
-                println!("  - {}", date);
-            }
+        let report = ScraperReport::new(name, days);
+        if format.is_text() {
+            print_summary(&report, compare_local);
         }
+        reports.push(report);
     }
     
-    Ok(())
+    match format {
+        OutputFormat::Text => {}
+        OutputFormat::Json => report::print_json(&VerifyReport { start_date, end_date, scrapers: &reports })?,
+        OutputFormat::Csv => report::print_csv(reports.iter().flat_map(|r| &r.days))?,
+    }
+    
+    let days = || reports.iter().flat_map(|r| &r.days);
+    let exit_code = if reports.iter().any(|r| r.error.is_some()) || days().any(|d| d.status == DayStatus::Error) {
+        EXIT_ERROR
+    } else if days().any(|d| d.status.is_gap()) {
+        EXIT_GAPS
+    } else {
+        EXIT_OK
+    };
+    std::process::exit(exit_code);
+}
+
+/// Result of checking one day, flat so it can be written as CSV.
+#[derive(Serialize)]
+struct DayResult {
+    scraper: String,
+    date: NaiveDate,
+    status: DayStatus,
+    detail: Option<String>,
+}
+
+#[derive(Serialize)]
+struct ScraperReport {
+    scraper: String,
+    checked_days: i64,
+    /// Set when the scraper's objects could not be listed at all
+    error: Option<String>,
+    counts: BTreeMap<DayStatus, usize>,
+    days: Vec<DayResult>,
+}
+
+impl ScraperReport {
+    fn new(scraper: String, days: Vec<DayResult>) -> Self {
+        let mut counts = BTreeMap::new();
+        for day in &days {
+            *counts.entry(day.status).or_insert(0) += 1;
+        }
+        Self { scraper, checked_days: days.len() as i64, error: None, counts, days }
+    }
+
+    fn failed(scraper: String, checked_days: i64, error: String) -> Self {
+        Self { scraper, checked_days, error: Some(error), counts: BTreeMap::new(), days: Vec::new() }
+    }
+
+    fn dates(&self, status: DayStatus) -> Vec<NaiveDate> {
+        self.days.iter().filter(|d| d.status == status).map(|d| d.date).collect()
+    }
+}
+
+#[derive(Serialize)]
+struct VerifyReport<'a> {
+    start_date: NaiveDate,
+    end_date: NaiveDate,
+    scrapers: &'a [ScraperReport],
+}
+
+/// One day of a scraper's data, locally and in S3.
+struct Partition {
+    date: NaiveDate,
+    s3_key: String,
+    local_path: String,
+}
+
+impl Partition {
+    fn new(date: NaiveDate, prefix: &str, data_dir: &str, base_folder: &str) -> Self {
+        let partition_path = format!("{}/year={}/month={:02}/day={:02}/data.parquet",
+            base_folder, date.year(), date.month(), date.day());
+        Self {
+            date,
+            s3_key: format!("{}{}", prefix, partition_path),
+            local_path: format!("{}/{}", data_dir, partition_path),
+        }
+    }
+}
+
+/// What is known about an uploaded partition. `sha256` is only filled in by HEAD requests.
+struct RemoteObject {
+    size: i64,
+    /// Unix seconds
+    last_modified: Option<i64>,
+    sha256: Option<String>,
+}
+
+/// List the partitions below `dataset_prefix` for every year in the range,
+/// a handful of paginated requests instead of one per day.
+async fn list_partitions(
+    client: &Client,
+    bucket: &str,
+    dataset_prefix: &str,
+    start_date: NaiveDate,
+    end_date: NaiveDate,
+) -> Result<HashMap<String, RemoteObject>> {
+    let mut objects = HashMap::new();
+
+    for year in start_date.year()..=end_date.year() {
+        let year_prefix = format!("{}/year={}/", dataset_prefix, year);
+        info!("Listing {}", year_prefix);
+
+        let mut pages = client
+            .list_objects_v2()
+            .bucket(bucket)
+            .prefix(&year_prefix)
+            .into_paginator()
+            .send();
+
+        while let Some(page) = pages.next().await {
+            for object in page?.contents() {
+                if let Some(key) = object.key().filter(|key| key.ends_with("/data.parquet")) {
+                    objects.insert(key.to_string(), RemoteObject {
+                        size: object.size().unwrap_or(0),
+                        last_modified: object.last_modified().map(|t| t.secs()),
+                        sha256: None,
+                    });
+                }
+            }
+        }
+    }
+
+    Ok(objects)
+}
+
+/// HEAD each key with at most `concurrency` requests in flight. Keys that don't exist
+/// are left out of both maps; other failures are returned with their error.
+///
+/// Without list permission S3 answers 403 instead of 404 for missing keys, so with
+/// `forbidden_is_missing` a 403 counts as not found as well.
+async fn head_objects(
+    client: &Client,
+    bucket: &str,
+    keys: Vec<String>,
+    concurrency: usize,
+    forbidden_is_missing: bool,
+    show_progress: bool,
+) -> (HashMap<String, RemoteObject>, HashMap<String, String>) {
+    let pb = if show_progress { ProgressBar::new(keys.len() as u64) } else { ProgressBar::hidden() };
+    pb.set_style(
+        ProgressStyle::default_bar()
+            .template("{spinner:.green} [{elapsed_precise}] [{bar:40.cyan/blue}] {pos}/{len} objects")
+            .unwrap()
+            .progress_chars("#>-")
+    );
+
+    let results: Vec<(String, Result<RemoteObject, String>)> = stream::iter(keys)
+        .map(|key| {
+            let pb = pb.clone();
+            async move {
+                let result = client.head_object().bucket(bucket).key(&key).send().await;
+                pb.inc(1);
+                let result = result
+                    .map(|head| RemoteObject {
+                        size: head.content_length().unwrap_or(0),
+                        last_modified: head.last_modified().map(|t| t.secs()),
+                        sha256: head.metadata().and_then(|m| m.get(SHA256_METADATA_KEY)).cloned(),
+                    })
+                    .map_err(|e| {
+                        let forbidden = e.raw_response().is_some_and(|response| response.status().as_u16() == 403);
+                        let not_found = e.as_service_error().is_some_and(|e| e.is_not_found())
+                            || (forbidden_is_missing && forbidden);
+                        if not_found {
+                            info!("Not found: {}", key);
+                            String::new()
+                        } else {
+                            let message = redact::scrub(&format!("{}", DisplayErrorContext(&e)));
+                            warn!("Failed to look up {}: {}", key, message);
+                            message
+                        }
+                    });
+                (key, result)
+            }
+        })
+        .buffer_unordered(concurrency)
+        .collect()
+        .await;
+
+    pb.finish_and_clear();
+
+    let mut objects = HashMap::new();
+    let mut errors = HashMap::new();
+    for (key, result) in results {
+        match result {
+            Ok(object) => {
+                objects.insert(key, object);
+            }
+            Err(e) if !e.is_empty() => {
+                errors.insert(key, e);
+            }
+            Err(_) => {}
+        }
+    }
+    (objects, errors)
+}
+
+/// Outcome of checking one day.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "snake_case")]
+enum DayStatus {
+    /// Object exists (checksum matches, or was not compared)
+    Present,
+    /// Not in S3; with `--compare-local`, a local file that was never uploaded
+    Missing,
+    /// Local file differs from the object or was modified after the upload
+    StaleInS3,
+    /// Stored SHA-256 differs from the local file
+    ChecksumMismatch,
+    /// Uploaded before checksums were stored
+    NoChecksum,
+    /// Object exists but there is no local file, e.g. removed by retention
+    OnlyInS3,
+    InSync,
+    /// Neither side has data for the day
+    NoData,
+    /// The object could not be checked, e.g. access denied
+    Error,
+}
+
+impl DayStatus {
+    fn is_gap(self) -> bool {
+        matches!(self, DayStatus::Missing | DayStatus::StaleInS3 | DayStatus::ChecksumMismatch)
+    }
+}
+
+impl fmt::Display for DayStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            DayStatus::Present => "present",
+            DayStatus::Missing => "missing",
+            DayStatus::StaleInS3 => "stale in S3",
+            DayStatus::ChecksumMismatch => "checksum mismatch",
+            DayStatus::NoChecksum => "no checksum",
+            DayStatus::OnlyInS3 => "only in S3",
+            DayStatus::InSync => "in sync",
+            DayStatus::NoData => "no data",
+            DayStatus::Error => "error",
+        })
+    }
+}
+
+/// Classify a day by comparing the local partition with the S3 object.
+///
+/// Sizes are compared first, then the SHA-256 stored with the object. Objects uploaded
+/// without a checksum count as stale when the local file was modified after the upload.
+fn compare_with_local(local_path: &Path, remote: Option<&RemoteObject>) -> Result<DayStatus> {
+    let object = match (local_path.exists(), remote) {
+        (false, None) => return Ok(DayStatus::NoData),
+        (false, Some(_)) => return Ok(DayStatus::OnlyInS3),
+        (true, None) => return Ok(DayStatus::Missing),
+        (true, Some(object)) => object,
+    };
+
+    let metadata = std::fs::metadata(local_path)?;
+    if object.size != metadata.len() as i64 {
+        return Ok(DayStatus::StaleInS3);
+    }
+
+    let stale = match check_checksum(local_path, object.sha256.as_deref())? {
+        ChecksumStatus::Match => false,
+        ChecksumStatus::Mismatch => true,
+        ChecksumStatus::NotStored | ChecksumStatus::NoLocalFile => {
+            let local_modified = metadata.modified()?.duration_since(UNIX_EPOCH)?.as_secs() as i64;
+            object.last_modified.is_some_and(|uploaded| local_modified > uploaded)
+        }
+    };
+
+    Ok(if stale { DayStatus::StaleInS3 } else { DayStatus::InSync })
+}
+
+fn print_summary(report: &ScraperReport, compare_local: bool) {
+    let print_dates = |heading: String, dates: &[NaiveDate]| {
+        println!("{}", heading);
+        for date in dates {
+            println!("  - {}", date);
+        }
+    };
+
+    if compare_local {
+        let count = |status| report.counts.get(&status).copied().unwrap_or(0);
+        println!("{} in sync, {} missing, {} stale in S3, {} only in S3, {} without data",
+            count(DayStatus::InSync), count(DayStatus::Missing), count(DayStatus::StaleInS3),
+            count(DayStatus::OnlyInS3), count(DayStatus::NoData));
+
+        for status in [DayStatus::Missing, DayStatus::StaleInS3, DayStatus::OnlyInS3] {
+            let dates = report.dates(status);
+            if !dates.is_empty() {
+                print_dates(format!("⚠ {} ({}):", status, dates.len()), &dates);
+            }
+        }
+    } else {
+        let missing = report.dates(DayStatus::Missing);
+        if missing.is_empty() {
+            println!("✓ All {} days present in S3", report.checked_days);
+        } else {
+            print_dates(format!("⚠ Missing {} of {} days:", missing.len(), report.checked_days), &missing);
+        }
+
+        let mismatched = report.dates(DayStatus::ChecksumMismatch);
+        if !mismatched.is_empty() {
+            print_dates(format!("✗ {} days differ from the local file (truncated or stale in S3):", mismatched.len()), &mismatched);
+        }
+        let unchecked = report.dates(DayStatus::NoChecksum);
+        if !unchecked.is_empty() {
+            print_dates(format!("? {} days have no checksum stored (uploaded before checksums were added):", unchecked.len()), &unchecked);
+        }
+    }
+
+    let errors: Vec<&DayResult> = report.days.iter().filter(|d| d.status == DayStatus::Error).collect();
+    if !errors.is_empty() {
+        println!("✗ {} days could not be checked:", errors.len());
+        for day in errors {
+            println!("  - {}: {}", day.date, day.detail.as_deref().unwrap_or_default());
+        }
+    }
+}
+
+enum ChecksumStatus {
+    Match,
+    Mismatch,
+    /// Uploaded before checksums were stored
+    NotStored,
+    NoLocalFile,
+}
+
+/// Compare the hex SHA-256 from the object metadata with the local partition file.
+fn check_checksum(local_path: &Path, remote_sha256: Option<&str>) -> Result<ChecksumStatus> {
+    if !local_path.exists() {
+        return Ok(ChecksumStatus::NoLocalFile);
+    }
+    let Some(remote_sha256) = remote_sha256 else {
+        return Ok(ChecksumStatus::NotStored);
+    };
+
+    let local_sha256 = hex(&file_sha256(local_path)?);
+    Ok(if local_sha256.eq_ignore_ascii_case(remote_sha256) {
+        ChecksumStatus::Match
+    } else {
+        ChecksumStatus::Mismatch
+    })
 }
@@ -0,0 +1,92 @@
+use anyhow::{Context, Result};
+use chrono::{DateTime, NaiveDate, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::{Path, PathBuf};
+
+use crate::redact;
+
+/// Default directory for backfill checkpoints
+pub const DEFAULT_STATE_DIR: &str = "state";
+
+/// Progress of a scraper's backfill, saved after every chunk of days so an interrupted
+/// run can be resumed with `--resume`.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Checkpoint {
+    pub scraper: String,
+    pub completed: BTreeSet<NaiveDate>,
+    /// Failed days with the (redacted) error of the last attempt
+    pub failed: BTreeMap<NaiveDate, String>,
+    pub updated_at: DateTime<Utc>,
+    #[serde(skip)]
+    path: PathBuf,
+}
+
+impl Checkpoint {
+    /// Empty checkpoint for `scraper`; nothing is written until the first day is recorded.
+    pub fn new(state_dir: &Path, scraper: &str) -> Self {
+        Self {
+            scraper: scraper.to_string(),
+            completed: BTreeSet::new(),
+            failed: BTreeMap::new(),
+            updated_at: Utc::now(),
+            path: Self::path(state_dir, scraper),
+        }
+    }
+
+    /// Load the checkpoint of `scraper`, or an empty one if there is none yet.
+    pub fn load(state_dir: &Path, scraper: &str) -> Result<Self> {
+        let path = Self::path(state_dir, scraper);
+        if !path.exists() {
+            return Ok(Self::new(state_dir, scraper));
+        }
+
+        let content = std::fs::read_to_string(&path)
+            .with_context(|| format!("Failed to read checkpoint {:?}", path))?;
+        let mut checkpoint: Self = serde_json::from_str(&content)
+            .with_context(|| format!("Failed to parse checkpoint {:?}", path))?;
+        checkpoint.path = path;
+        Ok(checkpoint)
+    }
+
+    fn path(state_dir: &Path, scraper: &str) -> PathBuf {
+        state_dir.join(format!("backfill_{}.json", scraper))
+    }
+
+    pub fn is_completed(&self, date: NaiveDate) -> bool {
+        self.completed.contains(&date)
+    }
+
+    /// Record a completed day; written by the next `save`.
+    pub fn mark_completed(&mut self, date: NaiveDate) {
+        self.failed.remove(&date);
+        self.completed.insert(date);
+    }
+
+    /// Record a failed day; written by the next `save`.
+    pub fn mark_failed(&mut self, date: NaiveDate, error: &str) {
+        self.completed.remove(&date);
+        self.failed.insert(date, redact::scrub(error));
+    }
+
+    /// Write the checkpoint on the blocking pool.
+    pub async fn save(&mut self) -> Result<()> {
+        self.updated_at = Utc::now();
+        let content = serde_json::to_string_pretty(self)?;
+        let path = self.path.clone();
+
+        tokio::task::spawn_blocking(move || -> Result<()> {
+            if let Some(parent) = path.parent() {
+                std::fs::create_dir_all(parent)?;
+            }
+
+            let tmp_path = path.with_extension("tmp");
+            std::fs::write(&tmp_path, content)?;
+
+            // Atomic rename
+            std::fs::rename(&tmp_path, &path)
+                .with_context(|| format!("Failed to write checkpoint {:?}", path))?;
+            Ok(())
+        }).await?
+    }
+}
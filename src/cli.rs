@@ -0,0 +1,341 @@
+use anyhow::{anyhow, bail, Result};
+use chrono::{Datelike, Duration, Months, NaiveDate, Utc};
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::str::FromStr;
+
+use crate::report::EXIT_ERROR;
+use crate::storage::DEFAULT_DATA_DIR;
+use crate::window::local_date;
+
+pub const DEFAULT_CONFIG_PATH: &str = "config.json";
+
+/// An option a binary accepts. Options with a `value` take one, as `--name value` or `--name=value`.
+pub struct OptionSpec {
+    pub name: &'static str,
+    /// Placeholder shown in the help, None for switches
+    pub value: Option<&'static str>,
+    pub help: &'static str,
+}
+
+pub const CONFIG: OptionSpec = OptionSpec {
+    name: "config",
+    value: Some("PATH"),
+    help: "Config file (default config.json)",
+};
+
+pub const DATA_DIR: OptionSpec = OptionSpec {
+    name: "data-dir",
+    value: Some("DIR"),
+    help: "Local data directory, mirrored below the S3 prefix (default data)",
+};
+
+pub const FROM: OptionSpec = OptionSpec {
+    name: "from",
+    value: Some("DATE"),
+    help: "First day: YYYY-MM-DD, today, yesterday, week-start, month-start, year-start, an offset \
+           like -30d, -2w, -1M or -1y, or both (month-start-1M)",
+};
+
+pub const TO: OptionSpec = OptionSpec {
+    name: "to",
+    value: Some("DATE"),
+    help: "Last day, same formats as --from (default today)",
+};
+
+pub const FORMAT: OptionSpec = OptionSpec {
+    name: "format",
+    value: Some("text|json|csv"),
+    help: "Print a JSON or CSV report on stdout instead of progress output",
+};
+
+/// Usage and options of a binary, used to parse its arguments and print `--help`.
+pub struct Command {
+    pub name: &'static str,
+    pub about: &'static str,
+    /// Positional arguments, e.g. `<scrapers> [<start_date> <end_date>]`
+    pub usage: &'static str,
+    pub options: &'static [OptionSpec],
+    pub examples: &'static [&'static str],
+}
+
+impl Command {
+    /// Parse the process arguments. Prints the help and exits on `--help`, and prints the
+    /// error and exits with `EXIT_ERROR` when the arguments are invalid.
+    pub fn parse_env(&self) -> Args {
+        match self.parse(std::env::args().skip(1)) {
+            Ok(Some(args)) => args,
+            Ok(None) => {
+                print!("{}", self.help());
+                std::process::exit(0);
+            }
+            Err(e) => self.exit_with_usage(format!("{:#}", e)),
+        }
+    }
+
+    /// Print `error` with the usage line and exit with `EXIT_ERROR`.
+    pub fn exit_with_usage(&self, error: impl fmt::Display) -> ! {
+        eprintln!("Error: {}", error);
+        eprintln!("Usage: {} {}", self.name, self.usage);
+        eprintln!("Run '{} --help' for all options", self.name);
+        std::process::exit(EXIT_ERROR);
+    }
+
+    /// Parse `args` (without the program name), None when `--help` was given.
+    pub fn parse(&self, args: impl IntoIterator<Item = String>) -> Result<Option<Args>> {
+        let mut parsed = Args::default();
+        let mut args = args.into_iter();
+
+        while let Some(arg) = args.next() {
+            if arg == "--help" || arg == "-h" {
+                return Ok(None);
+            }
+            let Some(option) = arg.strip_prefix("--") else {
+                parsed.positional.push(arg);
+                continue;
+            };
+
+            let (name, inline_value) = match option.split_once('=') {
+                Some((name, value)) => (name, Some(value.to_string())),
+                None => (option, None),
+            };
+            let spec = self.options.iter()
+                .find(|spec| spec.name == name)
+                .ok_or_else(|| anyhow!("Unknown option --{}", name))?;
+
+            if spec.value.is_some() {
+                let value = match inline_value {
+                    Some(value) => value,
+                    None => args.next().ok_or_else(|| anyhow!("--{} needs a value", name))?,
+                };
+                parsed.values.insert(spec.name, value);
+            } else if inline_value.is_some() {
+                bail!("--{} doesn't take a value", name);
+            } else {
+                parsed.switches.insert(spec.name);
+            }
+        }
+
+        Ok(Some(parsed))
+    }
+
+    pub fn help(&self) -> String {
+        let mut help = format!("{}\n\nUsage: {} {}\n\nOptions:\n", self.about, self.name, self.usage);
+        let option_label = |spec: &OptionSpec| match spec.value {
+            Some(value) => format!("--{} <{}>", spec.name, value),
+            None => format!("--{}", spec.name),
+        };
+        let width = self.options.iter().map(|spec| option_label(spec).len()).max().unwrap_or(0).max(6);
+
+        for spec in self.options {
+            help.push_str(&format!("  {:width$}  {}\n", option_label(spec), spec.help, width = width));
+        }
+        help.push_str(&format!("  {:width$}  {}\n", "--help", "Print this help", width = width));
+
+        if !self.examples.is_empty() {
+            help.push_str("\nExamples:\n");
+            for example in self.examples {
+                help.push_str(&format!("  {} {}\n", self.name, example));
+            }
+        }
+        help
+    }
+}
+
+/// Parsed arguments of a `Command`.
+#[derive(Debug, Default)]
+pub struct Args {
+    /// Arguments that are not options, in order
+    pub positional: Vec<String>,
+    values: HashMap<&'static str, String>,
+    switches: HashSet<&'static str>,
+}
+
+impl Args {
+    pub fn flag(&self, name: &str) -> bool {
+        self.switches.contains(name)
+    }
+
+    pub fn value(&self, name: &str) -> Option<&str> {
+        self.values.get(name).map(String::as_str)
+    }
+
+    /// Value of `--name` parsed as `T`, None when the option is absent.
+    pub fn parse_value<T: FromStr>(&self, name: &str) -> Result<Option<T>> {
+        self.value(name)
+            .map(|value| value.parse().map_err(|_| anyhow!("Invalid value '{}' for --{}", value, name)))
+            .transpose()
+    }
+
+    pub fn config_path(&self) -> &str {
+        self.value(CONFIG.name).unwrap_or(DEFAULT_CONFIG_PATH)
+    }
+
+    pub fn data_dir(&self) -> &str {
+        self.value(DATA_DIR.name).unwrap_or(DEFAULT_DATA_DIR)
+    }
+
+    /// Days from `--from` and `--to`, or from the positional arguments at `index` and
+    /// `index + 1` as the binaries accepted before these options existed.
+    pub fn date_range(&self, index: usize) -> Result<(NaiveDate, NaiveDate)> {
+        let today = local_today();
+        let (from, to) = match (self.value(FROM.name), self.positional.get(index)) {
+            (Some(_), Some(date)) => bail!("Unexpected date '{}', pass either --from or <start_date> <end_date>", date),
+            (Some(from), None) => (from, self.value(TO.name).unwrap_or("today")),
+            (None, Some(from)) => {
+                let to = self.value(TO.name).or(self.positional.get(index + 1).map(String::as_str))
+                    .ok_or_else(|| anyhow!("Missing end date, pass --to or <end_date>"))?;
+                (from.as_str(), to)
+            }
+            (None, None) => bail!("Missing date range, pass --from (and optionally --to)"),
+        };
+
+        let start_date = parse_date(from, today)?;
+        let end_date = parse_date(to, today)?;
+        if end_date < start_date {
+            bail!("End date {} is before start date {}", end_date, start_date);
+        }
+        Ok((start_date, end_date))
+    }
+}
+
+/// Today in Europe/Vienna, the days data is partitioned by.
+pub fn local_today() -> NaiveDate {
+    local_date(Utc::now(), &chrono_tz::Europe::Vienna)
+}
+
+/// Parse an absolute (`2025-01-31`) or relative date: a keyword (`today`, `yesterday`,
+/// `tomorrow`, `week-start`, `month-start`, `year-start`), an offset from today (`-30d`, `+2w`,
+/// `-1M`, `-1y`), or a keyword followed by an offset (`month-start-1M`).
+pub fn parse_date(expr: &str, today: NaiveDate) -> Result<NaiveDate> {
+    let expr = expr.trim();
+    if let Ok(date) = NaiveDate::parse_from_str(expr, "%Y-%m-%d") {
+        return Ok(date);
+    }
+
+    let invalid = || anyhow!(
+        "Invalid date '{}', expected YYYY-MM-DD, a keyword like yesterday or month-start, \
+         an offset like -30d, or both like month-start-1M", expr);
+
+    let anchors: [(&str, NaiveDate); 6] = [
+        ("today", today),
+        ("yesterday", today - Duration::days(1)),
+        ("tomorrow", today + Duration::days(1)),
+        ("week-start", today - Duration::days(today.weekday().num_days_from_monday() as i64)),
+        ("month-start", today.with_day(1).ok_or_else(invalid)?),
+        ("year-start", today.with_ordinal(1).ok_or_else(invalid)?),
+    ];
+    let (anchor, offset) = anchors.iter()
+        .find_map(|(keyword, date)| expr.strip_prefix(keyword).map(|rest| (*date, rest)))
+        .unwrap_or((today, expr));
+    if offset.is_empty() {
+        return Ok(anchor);
+    }
+
+    let (negative, offset) = match offset.as_bytes()[0] {
+        b'-' => (true, &offset[1..]),
+        b'+' => (false, &offset[1..]),
+        _ => return Err(invalid()),
+    };
+    let split = offset.find(|c: char| !c.is_ascii_digit()).unwrap_or(offset.len());
+    let (amount, unit) = offset.split_at(split);
+    let amount: u32 = amount.parse().map_err(|_| invalid())?;
+
+    // Out-of-range dates are invalid rather than a panic
+    let days = |per_unit: i64| Duration::try_days(amount as i64 * per_unit);
+    let months = |per_unit: u32| amount.checked_mul(per_unit).map(Months::new);
+    let date = match (unit, negative) {
+        ("d", true) => days(1).and_then(|days| anchor.checked_sub_signed(days)),
+        ("d", false) => days(1).and_then(|days| anchor.checked_add_signed(days)),
+        ("w", true) => days(7).and_then(|days| anchor.checked_sub_signed(days)),
+        ("w", false) => days(7).and_then(|days| anchor.checked_add_signed(days)),
+        ("M", true) => months(1).and_then(|months| anchor.checked_sub_months(months)),
+        ("M", false) => months(1).and_then(|months| anchor.checked_add_months(months)),
+        ("y", true) => months(12).and_then(|months| anchor.checked_sub_months(months)),
+        ("y", false) => months(12).and_then(|months| anchor.checked_add_months(months)),
+        _ => None,
+    };
+    date.ok_or_else(invalid)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(y: i32, m: u32, d: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(y, m, d).unwrap()
+    }
+
+    /// Wednesday
+    const TODAY: (i32, u32, u32) = (2025, 3, 12);
+
+    fn parse(expr: &str) -> Result<NaiveDate> {
+        parse_date(expr, date(TODAY.0, TODAY.1, TODAY.2))
+    }
+
+    fn args(args: &[&str]) -> Args {
+        let command = Command {
+            name: "test",
+            about: "",
+            usage: "",
+            options: &[FROM, TO],
+            examples: &[],
+        };
+        command.parse(args.iter().map(|arg| arg.to_string())).unwrap().unwrap()
+    }
+
+    #[test]
+    fn absolute_dates() {
+        assert_eq!(parse("2024-02-29").unwrap(), date(2024, 2, 29));
+        assert!(parse("2025-02-30").is_err());
+    }
+
+    #[test]
+    fn keywords() {
+        assert_eq!(parse("today").unwrap(), date(2025, 3, 12));
+        assert_eq!(parse("yesterday").unwrap(), date(2025, 3, 11));
+        assert_eq!(parse("tomorrow").unwrap(), date(2025, 3, 13));
+        assert_eq!(parse("week-start").unwrap(), date(2025, 3, 10));
+        assert_eq!(parse("month-start").unwrap(), date(2025, 3, 1));
+        assert_eq!(parse("year-start").unwrap(), date(2025, 1, 1));
+        assert!(parse("someday").is_err());
+    }
+
+    #[test]
+    fn offsets() {
+        assert_eq!(parse("-30d").unwrap(), date(2025, 2, 10));
+        assert_eq!(parse("+2w").unwrap(), date(2025, 3, 26));
+        assert_eq!(parse("-1M").unwrap(), date(2025, 2, 12));
+        assert_eq!(parse("-1y").unwrap(), date(2024, 3, 12));
+        assert_eq!(parse("month-start-1M").unwrap(), date(2025, 2, 1));
+        assert_eq!(parse("year-start+1M").unwrap(), date(2025, 2, 1));
+        assert!(parse("-30").is_err());
+        assert!(parse("-d").is_err());
+        assert!(parse("-3h").is_err());
+    }
+
+    #[test]
+    fn month_offsets_clamp_to_the_end_of_the_month() {
+        assert_eq!(parse_date("-1M", date(2025, 3, 31)).unwrap(), date(2025, 2, 28));
+        assert_eq!(parse_date("+1M", date(2024, 1, 31)).unwrap(), date(2024, 2, 29));
+        assert_eq!(parse_date("+1y", date(2024, 2, 29)).unwrap(), date(2025, 2, 28));
+    }
+
+    #[test]
+    fn out_of_range_offsets_are_errors() {
+        assert!(parse("-99999999d").is_err());
+        assert!(parse("+99999999w").is_err());
+        assert!(parse("-4000000000y").is_err());
+        assert!(parse("+999999999M").is_err());
+    }
+
+    #[test]
+    fn date_range_rejects_from_with_positional_dates() {
+        assert_eq!(args(&["all", "--from", "2025-01-01", "--to", "2025-01-31"]).date_range(1).unwrap(),
+            (date(2025, 1, 1), date(2025, 1, 31)));
+        assert_eq!(args(&["all", "2025-01-01", "2025-01-31"]).date_range(1).unwrap(),
+            (date(2025, 1, 1), date(2025, 1, 31)));
+        assert!(args(&["all", "2025-01-01", "--from", "2025-01-02"]).date_range(1).is_err());
+        assert!(args(&["all", "--from", "2025-01-31", "--to", "2025-01-01"]).date_range(1).is_err());
+    }
+}
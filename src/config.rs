@@ -1,14 +1,97 @@
+use anyhow::{anyhow, bail, Context};
+use chrono::Duration;
+use chrono_tz::Tz;
 use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
 use std::env;
+use std::fmt;
 use ve_energy_scrapers::models::strategy_information_scraper_config::StrategyInformationScraperConfig;
 
-#[derive(Debug, Deserialize, Serialize, Clone)]
+use crate::alerting::{AlertingConfig, FeedAlertConfig};
+use crate::redact;
+use crate::uploader::UploadSettings;
+use crate::schedule::ScheduleConfig;
+use crate::window::{ChunkSize, ScrapeWindow, WindowConfig};
+
+#[derive(Deserialize, Serialize, Clone)]
 pub struct ScraperConfig {
     #[serde(flatten)]
     pub scraper_config: StrategyInformationScraperConfig,
     pub sub_data_folder: Option<String>,
+    /// Scraper implementation, see `scraper_factory::known_kinds`; guessed from the URL when absent
+    pub kind: Option<String>,
+    /// Cron or aligned-interval schedule; `task_generator_delay_ms` is used when absent
+    pub schedule: Option<ScheduleConfig>,
+    /// Date range passed to the scraper; one day back and ahead when absent
+    pub window: Option<WindowConfig>,
+    /// Max time without a successful scrape before /readyz reports this scraper as stale
+    pub stale_after: Option<String>,
+    /// Thresholds for staleness alerts sent to the `alerting` webhook
+    pub alert: Option<FeedAlertConfig>,
+    /// Days fetched per backfill request, e.g. `1d`, `7d` or `1M`
+    pub backfill_chunk: Option<String>,
+}
+
+/// Debug output with secret values (tokens, passwords, ...) replaced by `***`
+impl fmt::Debug for ScraperConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ScraperConfig")
+            .field("name", &self.scraper_config.name)
+            .field("workers", &self.scraper_config.workers)
+            .field("task_generator_delay_ms", &self.scraper_config.task_generator_delay_ms)
+            .field("values", &redact::redact_values(&self.scraper_config.values))
+            .field("sub_data_folder", &self.sub_data_folder)
+            .field("kind", &self.kind)
+            .field("schedule", &self.schedule)
+            .field("window", &self.window)
+            .field("stale_after", &self.stale_after)
+            .field("alert", &self.alert)
+            .field("backfill_chunk", &self.backfill_chunk)
+            .finish()
+    }
+}
+
+impl ScraperConfig {
+    /// Shift applied to the scrape window, read from the `time_offset_minutes` value.
+    ///
+    /// The scrapers no longer apply this themselves, so the caller has to move the date range.
+    pub fn time_offset(&self) -> anyhow::Result<Duration> {
+        let minutes = match self.scraper_config.values.get("time_offset_minutes") {
+            None | Some(Value::Null) => return Ok(Duration::zero()),
+            Some(Value::Number(n)) => n.as_i64()
+                .ok_or_else(|| anyhow!("time_offset_minutes must be a whole number, got {}", n))?,
+            Some(Value::String(s)) => s.trim().parse::<i64>()
+                .with_context(|| format!("time_offset_minutes must be a whole number, got '{}'", s))?,
+            Some(other) => bail!("time_offset_minutes must be a number or numeric string, got {}", other),
+        };
+        Duration::try_minutes(minutes)
+            .with_context(|| format!("time_offset_minutes {} is out of range", minutes))
+    }
+
+    /// Staleness threshold for readiness, falling back to the app-wide default.
+    pub fn stale_after(&self, default: Option<&str>) -> anyhow::Result<Duration> {
+        let value = self.stale_after.as_deref().or(default).unwrap_or("1h");
+        let stale_after = parse_duration(value).context("Invalid stale_after")?;
+        if stale_after <= Duration::zero() {
+            bail!("stale_after must be positive");
+        }
+        Ok(stale_after)
+    }
+
+    /// Scrape window including the configured time offset.
+    pub fn scrape_window(&self) -> anyhow::Result<ScrapeWindow> {
+        let window = ScrapeWindow::from_config(self.window.as_ref()).context("Invalid window")?;
+        Ok(window.shifted(self.time_offset()?))
+    }
+
+    /// Configured backfill chunk size, None when the backfill should pick its default.
+    pub fn backfill_chunk(&self) -> anyhow::Result<Option<ChunkSize>> {
+        self.backfill_chunk.as_deref().map(ChunkSize::parse).transpose()
+    }
 }
 
+/// Debug output is redacted through `ScraperConfig`'s Debug impl
 #[derive(Debug, Deserialize, Serialize)]
 pub struct AppConfig {
     pub s3_bucket: Option<String>,
@@ -17,6 +100,30 @@ pub struct AppConfig {
     pub s3_prefix: Option<String>,
     pub scrapers: Vec<ScraperConfig>,
     pub retention_days: Option<u64>,
+    /// Listen address for the /metrics, /healthz and /readyz endpoints
+    pub http_addr: Option<String>,
+    /// Default for the per-scraper `stale_after`, "1h" when absent
+    pub stale_after: Option<String>,
+    /// Webhook for staleness alerts on scrapers with an `alert` section
+    pub alerting: Option<AlertingConfig>,
+    /// Files uploaded to S3 in parallel, 4 when absent
+    pub upload_concurrency: Option<usize>,
+    /// Files above this size (MiB) are uploaded in parts, 64 when absent
+    pub multipart_threshold_mb: Option<u64>,
+    /// Size of each multipart part (MiB, at least 5), 16 when absent
+    pub multipart_part_size_mb: Option<u64>,
+    /// Failed attempts before a file is moved to the dead-letter list, 10 when absent
+    pub upload_max_attempts: Option<u32>,
+    /// Delay before retrying a failed upload, doubled per failure, "1m" when absent
+    pub upload_retry_base: Option<String>,
+    /// Upper bound for the retry delay, "1h" when absent
+    pub upload_retry_max: Option<String>,
+    /// Time allowed on shutdown for workers to finish and pending files to upload, "30s" when absent
+    pub shutdown_timeout: Option<String>,
+    /// Extra value keys to treat as secrets, in addition to token, password, api_key, ...
+    pub redact_keys: Option<Vec<String>>,
+    /// Requests per minute per API host for backfills, on top of the built-in ENTSO-E and APG limits
+    pub rate_limits: Option<HashMap<String, u32>>,
 }
 
 impl AppConfig {
@@ -42,10 +149,303 @@ impl AppConfig {
             .or_else(|| self.s3_prefix.clone())
             .unwrap_or_else(|| "data/".to_string())
     }
+    
+    pub fn upload_settings(&self) -> anyhow::Result<UploadSettings> {
+        const MIB: u64 = 1024 * 1024;
+        let defaults = UploadSettings::default();
+
+        let settings = UploadSettings {
+            concurrency: self.upload_concurrency.unwrap_or(defaults.concurrency),
+            multipart_threshold: self.multipart_threshold_mb.map(|mb| mb * MIB).unwrap_or(defaults.multipart_threshold),
+            part_size: self.multipart_part_size_mb.map(|mb| mb * MIB).unwrap_or(defaults.part_size),
+            max_attempts: self.upload_max_attempts.unwrap_or(defaults.max_attempts),
+            retry_base: parse_retry_delay(self.upload_retry_base.as_deref(), "upload_retry_base")?
+                .unwrap_or(defaults.retry_base),
+            retry_max: parse_retry_delay(self.upload_retry_max.as_deref(), "upload_retry_max")?
+                .unwrap_or(defaults.retry_max),
+        };
+
+        if settings.concurrency == 0 {
+            bail!("upload_concurrency must be at least 1");
+        }
+        // S3 rejects parts below 5 MiB (except the last one)
+        if settings.part_size < 5 * MIB {
+            bail!("multipart_part_size_mb must be at least 5");
+        }
+        if settings.max_attempts == 0 {
+            bail!("upload_max_attempts must be at least 1");
+        }
+        Ok(settings)
+    }
+
+    pub fn shutdown_timeout(&self) -> anyhow::Result<std::time::Duration> {
+        parse_duration(self.shutdown_timeout.as_deref().unwrap_or("30s"))
+            .context("Invalid shutdown_timeout")?
+            .to_std()
+            .context("shutdown_timeout must not be negative")
+    }
+    
+    /// Get HTTP listen address from env var HTTP_ADDR, falling back to config file, default "0.0.0.0:9100"
+    pub fn get_http_addr(&self) -> String {
+        env::var("HTTP_ADDR")
+            .ok()
+            .or_else(|| self.http_addr.clone())
+            .unwrap_or_else(|| "0.0.0.0:9100".to_string())
+    }
+
+    /// Scrapers matching `selector`, in config order: `all`, or a comma-separated list
+    /// of names and globs such as `apg_*`. Every entry must match at least one scraper.
+    pub fn select_scrapers(&self, selector: &str) -> anyhow::Result<Vec<&ScraperConfig>> {
+        if selector == "all" {
+            return Ok(self.scrapers.iter().collect());
+        }
+
+        let patterns: Vec<&str> = selector.split(',').map(str::trim).filter(|p| !p.is_empty()).collect();
+        for pattern in &patterns {
+            if !self.scrapers.iter().any(|s| glob_match(pattern, &s.scraper_config.name)) {
+                bail!("No scraper in the config matches '{}'", pattern);
+            }
+        }
+
+        Ok(self.scrapers.iter()
+            .filter(|s| patterns.iter().any(|pattern| glob_match(pattern, &s.scraper_config.name)))
+            .collect())
+    }
+}
+
+/// Match `name` against a pattern where `*` stands for any run of characters and `?` for one.
+fn glob_match(pattern: &str, name: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let name: Vec<char> = name.chars().collect();
+    let (mut p, mut n) = (0, 0);
+    // Position after the last `*` and the name position it is currently matched up to
+    let mut backtrack = None;
+
+    while n < name.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                backtrack = Some((p + 1, n));
+                p += 1;
+            }
+            Some(&c) if c == '?' || c == name[n] => {
+                p += 1;
+                n += 1;
+            }
+            _ => match backtrack {
+                Some((after_star, matched)) => {
+                    p = after_star;
+                    n = matched + 1;
+                    backtrack = Some((after_star, matched + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+
+    pattern[p..].iter().all(|&c| c == '*')
 }
 
 pub fn load_config(path: &str) -> anyhow::Result<AppConfig> {
     let content = std::fs::read_to_string(path)?;
-    let config: AppConfig = serde_json::from_str(&content)?;
+    let mut config: AppConfig = serde_json::from_str(&content)?;
+
+    // Resolve secrets before anything reads the scraper values
+    for scraper in &mut config.scrapers {
+        let name = scraper.scraper_config.name.clone();
+        for (key, value) in scraper.scraper_config.values.iter_mut() {
+            interpolate_value(value)
+                .with_context(|| format!("Failed to resolve '{}' for scraper '{}'", key, name))?;
+        }
+    }
+
+    if let Some(alerting) = &mut config.alerting {
+        alerting.webhook_url = interpolate_str(&alerting.webhook_url)
+            .context("Failed to resolve alerting.webhook_url")?;
+        // Webhook URLs (Slack, Teams) embed their credentials
+        redact::register_secret(&Value::String(alerting.webhook_url.clone()));
+    }
+
+    if let Some(keys) = &config.redact_keys {
+        redact::add_secret_keys(keys);
+    }
+    for scraper in &config.scrapers {
+        redact::register_secrets(&scraper.scraper_config.values);
+    }
+
+    for scraper in &config.scrapers {
+        scraper.scrape_window()
+            .with_context(|| format!("Invalid config for scraper '{}'", scraper.scraper_config.name))?;
+    }
+
     Ok(config)
 }
+
+/// Replace `${VAR}` with the environment variable `VAR` and `${file:/path}` with the
+/// contents of that file (trailing newline removed) in every string inside `value`.
+fn interpolate_value(value: &mut Value) -> anyhow::Result<()> {
+    match value {
+        Value::String(s) => *s = interpolate_str(s)?,
+        Value::Array(items) => {
+            for item in items {
+                interpolate_value(item)?;
+            }
+        }
+        Value::Object(map) => {
+            for item in map.values_mut() {
+                interpolate_value(item)?;
+            }
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+fn interpolate_str(input: &str) -> anyhow::Result<String> {
+    let mut output = String::with_capacity(input.len());
+    let mut rest = input;
+
+    while let Some(start) = rest.find("${") {
+        output.push_str(&rest[..start]);
+        let after = &rest[start + 2..];
+        let end = after.find('}')
+            .ok_or_else(|| anyhow!("Unterminated '${{' in '{}'", input))?;
+        let reference = &after[..end];
+
+        let resolved = if let Some(path) = reference.strip_prefix("file:") {
+            std::fs::read_to_string(path)
+                .with_context(|| format!("Failed to read secret file {}", path))?
+                .trim_end_matches(['\r', '\n'])
+                .to_string()
+        } else {
+            env::var(reference)
+                .with_context(|| format!("Environment variable {} is not set", reference))?
+        };
+
+        output.push_str(&resolved);
+        rest = &after[end + 1..];
+    }
+
+    output.push_str(rest);
+    Ok(output)
+}
+
+/// Parse a duration such as `30s`, `15m`, `2h` or `1d`. A leading `-` makes it negative.
+pub fn parse_duration(value: &str) -> anyhow::Result<Duration> {
+    let value = value.trim();
+    let (negative, unsigned) = match value.strip_prefix('-') {
+        Some(rest) => (true, rest),
+        None => (false, value),
+    };
+
+    let split = unsigned.find(|c: char| !c.is_ascii_digit()).unwrap_or(unsigned.len());
+    let (amount, unit) = unsigned.split_at(split);
+    let amount: i64 = amount.parse()
+        .with_context(|| format!("Invalid duration '{}', expected e.g. 30s, 15m, 2h or 1d", value))?;
+
+    let duration = match unit {
+        "s" => Duration::try_seconds(amount),
+        "m" => Duration::try_minutes(amount),
+        "h" => Duration::try_hours(amount),
+        "d" => Duration::try_days(amount),
+        _ => bail!("Invalid duration unit in '{}', expected s, m, h or d", value),
+    };
+    let duration = duration.with_context(|| format!("Duration '{}' is out of range", value))?;
+
+    Ok(if negative { -duration } else { duration })
+}
+
+fn parse_retry_delay(value: Option<&str>, key: &str) -> anyhow::Result<Option<std::time::Duration>> {
+    value.map(|v| {
+        parse_duration(v)
+            .with_context(|| format!("Invalid {}", key))?
+            .to_std()
+            .with_context(|| format!("{} must not be negative", key))
+    }).transpose()
+}
+
+pub fn parse_timezone(name: &str) -> anyhow::Result<Tz> {
+    name.parse::<Tz>().map_err(|_| anyhow!("Unknown timezone '{}'", name))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn durations() {
+        assert_eq!(parse_duration("30s").unwrap(), Duration::seconds(30));
+        assert_eq!(parse_duration("15m").unwrap(), Duration::minutes(15));
+        assert_eq!(parse_duration("-2h").unwrap(), Duration::hours(-2));
+        assert_eq!(parse_duration("1d").unwrap(), Duration::days(1));
+        assert!(parse_duration("15").is_err());
+        assert!(parse_duration("15x").is_err());
+        assert!(parse_duration("99999999999999d").is_err());
+    }
+
+    fn scraper(extra: Value) -> ScraperConfig {
+        let mut config = serde_json::json!({ "name": "test", "workers": 1, "task_generator_delay_ms": 1000 });
+        config.as_object_mut().unwrap().extend(extra.as_object().unwrap().clone());
+        serde_json::from_value(config).unwrap()
+    }
+
+    #[test]
+    fn time_offsets() {
+        let offset = |value: Value| scraper(serde_json::json!({ "time_offset_minutes": value })).time_offset();
+
+        assert_eq!(scraper(serde_json::json!({})).time_offset().unwrap(), Duration::zero());
+        assert_eq!(offset(serde_json::json!(-30)).unwrap(), Duration::minutes(-30));
+        assert_eq!(offset(serde_json::json!(" 60 ")).unwrap(), Duration::minutes(60));
+        assert!(offset(serde_json::json!("half an hour")).is_err());
+        assert!(offset(serde_json::json!(1.5)).is_err());
+        assert!(offset(serde_json::json!(true)).is_err());
+        assert!(offset(serde_json::json!("9999999999999999")).is_err());
+        assert!(offset(serde_json::json!(9_999_999_999_999_999i64)).is_err());
+    }
+
+    #[test]
+    fn glob_patterns() {
+        assert!(glob_match("apg_imb_15min", "apg_imb_15min"));
+        assert!(glob_match("*", "apg_imb_15min"));
+        assert!(glob_match("apg_*", "apg_imb_15min"));
+        assert!(glob_match("apg_???_15min", "apg_imb_15min"));
+        assert!(glob_match("a*b*c", "axxbyybzzc"));
+        assert!(glob_match("*_15min*", "entsoe_load_15min"));
+        assert!(!glob_match("apg_*", "entsoe_load"));
+        assert!(!glob_match("apg_?", "apg_imb"));
+        assert!(!glob_match("a*b*c", "axxcyyb"));
+        assert!(!glob_match("apg", "apg_imb"));
+    }
+
+    #[test]
+    fn scraper_selection() {
+        let scrapers: Vec<Value> = ["apg_imb_15min", "apg_load", "entsoe_load"].iter()
+            .map(|name| serde_json::json!({ "name": name, "workers": 1, "task_generator_delay_ms": 1000 }))
+            .collect();
+        let config: AppConfig = serde_json::from_value(serde_json::json!({ "scrapers": scrapers })).unwrap();
+        let names = |selector: &str| -> Vec<String> {
+            config.select_scrapers(selector).unwrap().iter().map(|s| s.scraper_config.name.clone()).collect()
+        };
+
+        assert_eq!(names("all"), ["apg_imb_15min", "apg_load", "entsoe_load"]);
+        assert_eq!(names("entsoe_load, apg_*"), ["apg_imb_15min", "apg_load", "entsoe_load"]);
+        assert_eq!(names("apg_load,apg_load"), ["apg_load"]);
+        assert!(config.select_scrapers("apg_*,epex_*").is_err());
+    }
+
+    #[test]
+    fn interpolation() {
+        env::set_var("CONFIG_TEST_TOKEN", "abc123");
+        env::remove_var("CONFIG_TEST_MISSING");
+        let secret_file = std::env::temp_dir().join(format!("config_test_secret_{}", std::process::id()));
+        std::fs::write(&secret_file, "from-file\n").unwrap();
+
+        assert_eq!(interpolate_str("token=${CONFIG_TEST_TOKEN}&x=1").unwrap(), "token=abc123&x=1");
+        assert_eq!(interpolate_str(&format!("${{file:{}}}", secret_file.display())).unwrap(), "from-file");
+        assert_eq!(interpolate_str("no references").unwrap(), "no references");
+        assert!(interpolate_str("${CONFIG_TEST_MISSING}").is_err());
+        assert!(interpolate_str("${file:/nonexistent/secret}").is_err());
+        assert!(interpolate_str("token=${CONFIG_TEST_TOKEN").is_err());
+
+        std::fs::remove_file(&secret_file).unwrap();
+    }
+}
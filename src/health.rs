@@ -0,0 +1,127 @@
+use chrono::{DateTime, Duration, Utc};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::RwLock;
+
+/// Liveness and readiness state reported by `/healthz` and `/readyz`.
+pub struct HealthState {
+    config_loaded: AtomicBool,
+    uploader_required: AtomicBool,
+    uploader_ready: AtomicBool,
+    scrapers: RwLock<BTreeMap<String, ScraperHealth>>,
+}
+
+struct ScraperHealth {
+    stale_after: Duration,
+    registered_at: DateTime<Utc>,
+    last_success: Option<DateTime<Utc>>,
+}
+
+#[derive(Serialize)]
+pub struct ScraperStatus {
+    pub last_success: Option<DateTime<Utc>>,
+    pub stale_after_secs: i64,
+    pub stale: bool,
+}
+
+#[derive(Serialize)]
+pub struct Readiness {
+    pub ready: bool,
+    pub config_loaded: bool,
+    /// "ready", "not_ready" or "disabled" when no S3 bucket is configured
+    pub uploader: &'static str,
+    pub stale_scrapers: Vec<String>,
+    pub scrapers: BTreeMap<String, ScraperStatus>,
+}
+
+impl Default for HealthState {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl HealthState {
+    pub fn new() -> Self {
+        Self {
+            config_loaded: AtomicBool::new(false),
+            uploader_required: AtomicBool::new(false),
+            uploader_ready: AtomicBool::new(false),
+            scrapers: RwLock::new(BTreeMap::new()),
+        }
+    }
+
+    pub fn mark_config_loaded(&self) {
+        self.config_loaded.store(true, Ordering::Relaxed);
+    }
+
+    /// Record that an uploader is configured; readiness waits for `mark_uploader_ready`.
+    pub fn require_uploader(&self) {
+        self.uploader_required.store(true, Ordering::Relaxed);
+    }
+
+    pub fn mark_uploader_ready(&self) {
+        self.uploader_ready.store(true, Ordering::Relaxed);
+    }
+
+    /// Track a scraper that must succeed at least every `stale_after`.
+    /// A scraper that never succeeded becomes stale `stale_after` after registration.
+    pub fn register_scraper(&self, name: &str, stale_after: Duration) {
+        let mut scrapers = self.scrapers.write().unwrap_or_else(|e| e.into_inner());
+        scrapers.insert(name.to_string(), ScraperHealth {
+            stale_after,
+            registered_at: Utc::now(),
+            last_success: None,
+        });
+    }
+
+    pub fn record_success(&self, name: &str) {
+        let mut scrapers = self.scrapers.write().unwrap_or_else(|e| e.into_inner());
+        if let Some(scraper) = scrapers.get_mut(name) {
+            scraper.last_success = Some(Utc::now());
+        }
+    }
+
+    pub fn readiness(&self) -> Readiness {
+        let now = Utc::now();
+        let scrapers: BTreeMap<String, ScraperStatus> = self.scrapers.read()
+            .unwrap_or_else(|e| e.into_inner())
+            .iter()
+            .map(|(name, scraper)| {
+                let since = scraper.last_success.unwrap_or(scraper.registered_at);
+                let status = ScraperStatus {
+                    last_success: scraper.last_success,
+                    stale_after_secs: scraper.stale_after.num_seconds(),
+                    stale: now - since > scraper.stale_after,
+                };
+                (name.clone(), status)
+            })
+            .collect();
+
+        let stale_scrapers: Vec<String> = scrapers.iter()
+            .filter(|(_, status)| status.stale)
+            .map(|(name, _)| name.clone())
+            .collect();
+
+        let config_loaded = self.config_loaded.load(Ordering::Relaxed);
+        let uploader = match (self.uploader_required.load(Ordering::Relaxed), self.uploader_ready.load(Ordering::Relaxed)) {
+            (false, _) => "disabled",
+            (true, true) => "ready",
+            (true, false) => "not_ready",
+        };
+
+        Readiness {
+            ready: config_loaded && uploader != "not_ready" && stale_scrapers.is_empty(),
+            config_loaded,
+            uploader,
+            stale_scrapers,
+            scrapers,
+        }
+    }
+
+    /// Alive unless every scraper is stale, which means all workers are stuck.
+    pub fn is_alive(&self) -> bool {
+        let readiness = self.readiness();
+        readiness.scrapers.is_empty() || readiness.stale_scrapers.len() < readiness.scrapers.len()
+    }
+}
@@ -0,0 +1,48 @@
+use anyhow::{Context, Result};
+use axum::extract::State;
+use axum::http::{header, StatusCode};
+use axum::response::IntoResponse;
+use axum::routing::get;
+use axum::{Json, Router};
+use std::sync::Arc;
+use tracing::info;
+
+use crate::health::HealthState;
+use crate::metrics::metrics;
+
+/// Serve the monitoring endpoints on `addr` until the process exits.
+pub async fn serve(addr: &str, health: Arc<HealthState>) -> Result<()> {
+    let app = Router::new()
+        .route("/metrics", get(metrics_handler))
+        .route("/healthz", get(healthz_handler))
+        .route("/readyz", get(readyz_handler))
+        .with_state(health);
+
+    let listener = tokio::net::TcpListener::bind(addr).await
+        .with_context(|| format!("Failed to bind HTTP server to {}", addr))?;
+    info!("Serving /metrics, /healthz and /readyz on http://{}", addr);
+
+    axum::serve(listener, app).await?;
+    Ok(())
+}
+
+async fn metrics_handler() -> impl IntoResponse {
+    (
+        [(header::CONTENT_TYPE, "text/plain; version=0.0.4")],
+        metrics().encode(),
+    )
+}
+
+async fn healthz_handler(State(health): State<Arc<HealthState>>) -> impl IntoResponse {
+    if health.is_alive() {
+        (StatusCode::OK, "ok")
+    } else {
+        (StatusCode::SERVICE_UNAVAILABLE, "all scrapers are stale")
+    }
+}
+
+async fn readyz_handler(State(health): State<Arc<HealthState>>) -> impl IntoResponse {
+    let readiness = health.readiness();
+    let status = if readiness.ready { StatusCode::OK } else { StatusCode::SERVICE_UNAVAILABLE };
+    (status, Json(readiness))
+}
@@ -1,4 +1,17 @@
 pub mod config;
 pub mod storage;
 pub mod uploader;
+pub mod upload_queue;
 pub mod scraper_factory;
+pub mod schedule;
+pub mod window;
+pub mod validation;
+pub mod redact;
+pub mod metrics;
+pub mod http;
+pub mod health;
+pub mod alerting;
+pub mod checkpoint;
+pub mod report;
+pub mod rate_limit;
+pub mod cli;
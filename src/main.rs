@@ -1,59 +1,132 @@
 use anyhow::{Context, Result};
-use tracing::{info, error};
+use tracing::{info, warn, error};
 use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt, Layer};
 use std::sync::Arc;
 use std::time::Duration;
 use tokio::sync::{mpsc, Mutex};
-use tokio::time::sleep;
-use chrono::{Duration as ChronoDuration, Utc};
+use tokio::task::JoinHandle;
+use tokio::time::{sleep, Instant};
+use tokio_util::sync::CancellationToken;
+use chrono::Utc;
 
-use scraping_service::{config, storage, uploader, scraper_factory};
+use scraping_service::{cli, config, storage, uploader, scraper_factory};
 use config::{load_config, ScraperConfig};
+use scraping_service::schedule::Schedule;
+use scraping_service::validation::validate_config;
+use scraping_service::redact::{self, RedactingMakeWriter};
+use scraping_service::metrics::metrics;
+use scraping_service::http;
+use scraping_service::health::HealthState;
+use scraping_service::alerting::{Alerter, FeedThresholds, FeedTracker};
 use storage::Storage;
 use uploader::Uploader;
 
+const COMMAND: cli::Command = cli::Command {
+    name: "scraping_service",
+    about: "Runs the configured scrapers, stores their data as Parquet and uploads it to S3.\n\
+            'validate' checks the config, reports every problem and exits.",
+    usage: "[validate [<config>]] [OPTIONS]",
+    options: &[cli::CONFIG, cli::DATA_DIR],
+    examples: &[
+        "--config /etc/scraping/config.json --data-dir /var/lib/scraping/data",
+        "validate --config config.prod.json",
+    ],
+};
+
 #[tokio::main]
 async fn main() -> Result<()> {
     // Load .env file in debug builds only
     #[cfg(debug_assertions)]
     dotenvy::dotenv().ok();
 
+    // `scraping_service validate [config.json]` checks the config and exits
+    let args = COMMAND.parse_env();
+    match args.positional.first().map(String::as_str) {
+        Some("validate") => {
+            let path = args.positional.get(1).map(String::as_str).unwrap_or(args.config_path());
+            std::process::exit(run_validate(path));
+        }
+        Some(other) => {
+            eprintln!("Error: unknown command '{}', run '{} --help' for usage", other, COMMAND.name);
+            std::process::exit(1);
+        }
+        None => {}
+    }
+
     let file_appender = tracing_appender::rolling::daily("logs", "service.log");
     let (non_blocking, _guard) = tracing_appender::non_blocking(file_appender);
 
     tracing_subscriber::registry()
         .with(
             tracing_subscriber::fmt::layer()
+                .with_writer(RedactingMakeWriter::new(std::io::stdout))
                 .with_filter(tracing_subscriber::EnvFilter::try_from_default_env().unwrap_or_else(|_| tracing_subscriber::EnvFilter::new("info")))
         )
         .with(
             tracing_subscriber::fmt::layer()
                 .json()
-                .with_writer(non_blocking)
+                .with_writer(RedactingMakeWriter::new(non_blocking))
                 .with_filter(tracing_subscriber::EnvFilter::try_from_default_env().unwrap_or_else(|_| tracing_subscriber::EnvFilter::new("info")))
         )
         .init();
 
-    let config = load_config("config.json").context("Failed to load config.json")?;
+    let config_path = args.config_path();
+    let config = load_config(config_path).with_context(|| format!("Failed to load {}", config_path))?;
+
+    let problems = validate_config(&config);
+    if !problems.is_empty() {
+        for problem in &problems {
+            error!("Invalid config: {}", problem);
+        }
+        anyhow::bail!("{} has {} problem(s), not starting", config_path, problems.len());
+    }
+
+    let health = Arc::new(HealthState::new());
+    health.mark_config_loaded();
     
+    let http_addr = config.get_http_addr();
+    let health_http = health.clone();
+    tokio::spawn(async move {
+        if let Err(e) = http::serve(&http_addr, health_http).await {
+            error!("HTTP server stopped: {:?}", e);
+        }
+    });
+
+    let shutdown = CancellationToken::new();
+    let shutdown_timeout = config.shutdown_timeout()?;
     let mut dirty_files_handle = None;
+    let mut uploader_handle = None;
     
     // Use env vars with fallback to config file values
     if let Some(bucket) = config.get_s3_bucket() {
+        health.require_uploader();
         let uploader = Uploader::new(
             bucket,
             config.get_s3_region(),
             config.get_s3_endpoint(),
             config.get_s3_prefix(),
+            args.data_dir(),
+            config.upload_settings()?,
         ).await?;
+        health.mark_uploader_ready();
         dirty_files_handle = Some(uploader.get_pending_files_handle());
         
-        tokio::spawn(async move {
-            uploader.run().await;
+        // Queue anything that changed locally while the service was not running
+        match uploader.reconcile().await {
+            Ok(queued) => info!("Startup reconciliation queued {} files for upload", queued),
+            Err(e) => warn!("Startup reconciliation failed: {:?}", e),
+        }
+        
+        let uploader = Arc::new(uploader);
+        let uploader_run = uploader.clone();
+        let shutdown_uploader = shutdown.clone();
+        let handle = tokio::spawn(async move {
+            uploader_run.run(shutdown_uploader).await;
         });
+        uploader_handle = Some((uploader, handle));
     }
 
-    let storage = Arc::new(Storage::new("data", dirty_files_handle));
+    let storage = Arc::new(Storage::new(args.data_dir(), dirty_files_handle));
 
     if let Some(retention_days) = config.retention_days {
         let storage_cleanup = storage.clone();
@@ -68,27 +141,125 @@ async fn main() -> Result<()> {
         });
     }
 
+    let feeds = Arc::new(FeedTracker::new());
+    if let Some(alerting) = &config.alerting {
+        let alerter = Alerter::new(alerting, feeds.clone())?;
+        tokio::spawn(async move {
+            alerter.run().await;
+        });
+    }
+
+    let mut worker_handles = Vec::new();
     for scraper_config in config.scrapers {
         let storage_clone = storage.clone();
-        if let Err(e) = start_scraper_pool(scraper_config, storage_clone).await {
-            error!("Failed to start scraper pool: {:?}", e);
+        let stale_after = scraper_config.stale_after(config.stale_after.as_deref())?;
+        health.register_scraper(&scraper_config.scraper_config.name, stale_after);
+        if let (Some(alert), Some(_)) = (&scraper_config.alert, &config.alerting) {
+            feeds.register(&scraper_config.scraper_config.name, FeedThresholds::from_config(alert)?);
+        }
+        match start_scraper_pool(scraper_config, storage_clone, health.clone(), feeds.clone(), shutdown.clone()).await {
+            Ok(handles) => worker_handles.extend(handles),
+            Err(e) => error!("Failed to start scraper pool: {:?}", e),
         }
     }
 
     // Keep the main thread alive
+    wait_for_shutdown_signal().await?;
+    info!("Shutting down, waiting up to {}s for workers and uploads", shutdown_timeout.as_secs());
+    let deadline = Instant::now() + shutdown_timeout;
+
+    // Stop the task generators; workers finish their current scrape and save
+    shutdown.cancel();
+    let workers_done = tokio::time::timeout_at(deadline, async {
+        for handle in &mut worker_handles {
+            let _ = handle.await;
+        }
+    }).await;
+    if workers_done.is_err() {
+        warn!("Workers did not finish before the shutdown deadline, aborting them");
+        for handle in &worker_handles {
+            handle.abort();
+        }
+    }
+
+    // Upload whatever the workers saved, including files still queued from earlier cycles
+    if let Some((uploader, mut handle)) = uploader_handle {
+        let cycle_aborted = tokio::time::timeout_at(deadline, &mut handle).await.is_err();
+        if cycle_aborted {
+            warn!("Upload cycle still running at the shutdown deadline, aborting it");
+            handle.abort();
+        }
+        let remaining = uploader.finish(deadline).await;
+        if remaining > 0 {
+            warn!("{} files not uploaded before shutdown, they stay in the upload journal", remaining);
+        }
+        if cycle_aborted {
+            // Its drained files are neither queued nor uploaded, but still in the journal
+            warn!("Files of the aborted upload cycle stay in the upload journal for the next start");
+        } else if remaining == 0 {
+            info!("All pending files uploaded");
+        }
+    }
+
+    info!("Shutdown complete");
+    Ok(())
+}
+
+/// Resolve on Ctrl-C or SIGTERM (sent by `docker stop`).
+async fn wait_for_shutdown_signal() -> Result<()> {
+    #[cfg(unix)]
+    {
+        let mut sigterm = tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())?;
+        tokio::select! {
+            result = tokio::signal::ctrl_c() => result?,
+            _ = sigterm.recv() => {}
+        }
+    }
+    #[cfg(not(unix))]
     tokio::signal::ctrl_c().await?;
-    info!("Shutting down");
 
     Ok(())
 }
 
-async fn start_scraper_pool(config: ScraperConfig, storage: Arc<Storage>) -> Result<()> {
+/// Validate the config at `path`, print every problem and return the process exit code.
+fn run_validate(path: &str) -> i32 {
+    let config = match load_config(path) {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("✗ Failed to load {}: {}", path, redact::scrub(&format!("{:#}", e)));
+            return 1;
+        }
+    };
+
+    let problems = validate_config(&config);
+    if problems.is_empty() {
+        println!("✓ {} is valid ({} scrapers)", path, config.scrapers.len());
+        0
+    } else {
+        eprintln!("✗ {} has {} problem(s):", path, problems.len());
+        for problem in &problems {
+            eprintln!("  - {}", redact::scrub(problem));
+        }
+        1
+    }
+}
+
+async fn start_scraper_pool(
+    config: ScraperConfig,
+    storage: Arc<Storage>,
+    health: Arc<HealthState>,
+    feeds: Arc<FeedTracker>,
+    shutdown: CancellationToken,
+) -> Result<Vec<JoinHandle<()>>> {
     let name = config.scraper_config.name.clone();
     let workers = config.scraper_config.workers;
-    let delay = config.scraper_config.task_generator_delay_ms as u64;
+    let schedule = Schedule::from_config(config.schedule.as_ref(), config.scraper_config.task_generator_delay_ms)
+        .with_context(|| format!("Invalid schedule for {}", name))?;
+    let window = config.scrape_window()
+        .with_context(|| format!("Invalid scrape window for {}", name))?;
     let subfolder = config.sub_data_folder.clone();
 
-    let scraper = scraper_factory::create_scraper(&config.scraper_config)?;
+    let scraper = scraper_factory::create_scraper(&config)?;
     let scraper = Arc::new(scraper);
     
     // Create a channel for tasks. The buffer size can be adjusted.
@@ -97,21 +268,26 @@ async fn start_scraper_pool(config: ScraperConfig, storage: Arc<Storage>) -> Res
     let (tx, rx) = mpsc::channel::<()>(buffer_size);
     let rx = Arc::new(Mutex::new(rx));
 
-    info!("Starting scraper pool for {}: {} workers, {}ms delay", name, workers, delay);
+    info!("Starting scraper pool for {}: {} workers, {}", name, workers, schedule);
 
-    // Task Generator
+    // Task Generator, dropping the sender on shutdown closes the channel for the workers
     let name_gen = name.clone();
+    let shutdown_gen = shutdown.clone();
     tokio::spawn(async move {
         loop {
             if tx.send(()).await.is_err() {
                 error!("Receiver dropped for {}, stopping generator", name_gen);
                 break;
             }
-            sleep(Duration::from_millis(delay)).await;
+            tokio::select! {
+                _ = shutdown_gen.cancelled() => break,
+                _ = sleep(schedule.delay_until_next(Utc::now())) => {}
+            }
         }
     });
 
     // Workers
+    let mut handles = Vec::new();
     for i in 0..workers {
         let rx = rx.clone();
         let scraper = scraper.clone();
@@ -119,8 +295,12 @@ async fn start_scraper_pool(config: ScraperConfig, storage: Arc<Storage>) -> Res
         let worker_name = format!("{}-worker-{}", name, i);
         let scraper_name = name.clone();
         let subfolder = subfolder.clone();
+        let window = window.clone();
+        let health = health.clone();
+        let feeds = feeds.clone();
+        let shutdown = shutdown.clone();
 
-        tokio::spawn(async move {
+        handles.push(tokio::spawn(async move {
             loop {
                 // Acquire lock just to get the task
                 {
@@ -130,19 +310,35 @@ async fn start_scraper_pool(config: ScraperConfig, storage: Arc<Storage>) -> Res
                     }
                 } // Lock released here
 
-                // Define date range: yesterday, today, and tomorrow
-                let now = Utc::now();
-                let start_date = now - ChronoDuration::days(1); // Yesterday
-                let end_date = now + ChronoDuration::days(1);   // Tomorrow
+                // Don't start queued tasks once shutdown has begun
+                if shutdown.is_cancelled() {
+                    break;
+                }
+
+                // Date range from the scraper's window, by default yesterday to tomorrow
+                let (start_date, end_date) = window.range(Utc::now());
 
                 // Perform the scrape
-                match scraper.scrape_data(start_date, end_date).await {
+                metrics().scrape_attempts.with_label_values(&[&scraper_name]).inc();
+                let timer = metrics().scrape_duration.with_label_values(&[&scraper_name]).start_timer();
+                let result = scraper.scrape_data(start_date, end_date).await;
+                timer.observe_duration();
+
+                match result {
                     Ok(data) => {
+                        metrics().rows_scraped.with_label_values(&[&scraper_name]).inc_by(data.len() as u64);
+                        metrics().last_scrape_success.with_label_values(&[&scraper_name])
+                            .set(Utc::now().timestamp() as f64);
+                        health.record_success(&scraper_name);
+                        if let Some(newest) = data.iter().map(|d| d.delivery_to).max() {
+                            feeds.record_scrape(&scraper_name, newest);
+                        }
                         if !data.is_empty() {
                             match storage.save_if_new(&scraper_name, subfolder.as_deref(), &data).await {
                                 Ok(saved) => {
                                     if saved {
                                         info!("[{}] Saved new data", worker_name);
+                                        feeds.record_new_data(&scraper_name);
                                     }
                                 }
                                 Err(e) => error!("[{}] Failed to save data: {:?}", worker_name, e),
@@ -150,13 +346,14 @@ async fn start_scraper_pool(config: ScraperConfig, storage: Arc<Storage>) -> Res
                         }
                     }
                     Err(e) => {
+                        metrics().scrape_failures.with_label_values(&[&scraper_name]).inc();
                         error!("[{}] Error scraping: {:?}", worker_name, e);
                     }
                 }
             }
-        });
+        }));
     }
 
-    Ok(())
+    Ok(handles)
 }
 
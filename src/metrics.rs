@@ -0,0 +1,98 @@
+use prometheus::{
+    exponential_buckets, Encoder, GaugeVec, Gauge, HistogramOpts, HistogramVec, IntCounter, IntCounterVec,
+    IntGauge, Opts, Registry, TextEncoder,
+};
+use std::sync::OnceLock;
+
+/// Prometheus metrics shared by the scraper pools, `Storage` and `Uploader`
+pub struct Metrics {
+    registry: Registry,
+    pub scrape_attempts: IntCounterVec,
+    pub scrape_failures: IntCounterVec,
+    pub scrape_duration: HistogramVec,
+    pub rows_scraped: IntCounterVec,
+    pub last_scrape_success: GaugeVec,
+    pub partitions_changed: IntCounterVec,
+    pub upload_queue_depth: IntGauge,
+    pub uploads: IntCounter,
+    pub upload_failures: IntCounter,
+    pub upload_dead_letters: IntGauge,
+    pub bytes_uploaded: IntCounter,
+    pub last_upload_success: Gauge,
+}
+
+static METRICS: OnceLock<Metrics> = OnceLock::new();
+
+pub fn metrics() -> &'static Metrics {
+    METRICS.get_or_init(|| Metrics::new().expect("metric definitions are valid"))
+}
+
+impl Metrics {
+    fn new() -> prometheus::Result<Self> {
+        let registry = Registry::new_custom(Some("scraping_service".to_string()), None)?;
+
+        let scrape_attempts = IntCounterVec::new(
+            Opts::new("scrape_attempts_total", "Scrape calls started"), &["scraper"])?;
+        let scrape_failures = IntCounterVec::new(
+            Opts::new("scrape_failures_total", "Scrape calls that returned an error"), &["scraper"])?;
+        let scrape_duration = HistogramVec::new(
+            HistogramOpts::new("scrape_duration_seconds", "Time spent in scrape_data")
+                .buckets(exponential_buckets(0.05, 2.0, 12)?),
+            &["scraper"],
+        )?;
+        let rows_scraped = IntCounterVec::new(
+            Opts::new("rows_scraped_total", "Rows returned by successful scrapes"), &["scraper"])?;
+        let last_scrape_success = GaugeVec::new(
+            Opts::new("last_scrape_success_timestamp_seconds", "Unix time of the last successful scrape"),
+            &["scraper"],
+        )?;
+        let partitions_changed = IntCounterVec::new(
+            Opts::new("partitions_changed_total", "Parquet partitions rewritten by Storage"), &["scraper"])?;
+        let upload_queue_depth = IntGauge::new("upload_queue_depth", "Files waiting to be uploaded")?;
+        let uploads = IntCounter::new("uploads_total", "Files uploaded to S3")?;
+        let upload_failures = IntCounter::new("upload_failures_total", "Failed upload attempts")?;
+        let upload_dead_letters = IntGauge::new(
+            "upload_dead_letters", "Files given up on after permanent errors or too many attempts")?;
+        let bytes_uploaded = IntCounter::new("uploaded_bytes_total", "Bytes uploaded to S3")?;
+        let last_upload_success = Gauge::new(
+            "last_upload_success_timestamp_seconds", "Unix time of the last successful upload")?;
+
+        registry.register(Box::new(scrape_attempts.clone()))?;
+        registry.register(Box::new(scrape_failures.clone()))?;
+        registry.register(Box::new(scrape_duration.clone()))?;
+        registry.register(Box::new(rows_scraped.clone()))?;
+        registry.register(Box::new(last_scrape_success.clone()))?;
+        registry.register(Box::new(partitions_changed.clone()))?;
+        registry.register(Box::new(upload_queue_depth.clone()))?;
+        registry.register(Box::new(uploads.clone()))?;
+        registry.register(Box::new(upload_failures.clone()))?;
+        registry.register(Box::new(upload_dead_letters.clone()))?;
+        registry.register(Box::new(bytes_uploaded.clone()))?;
+        registry.register(Box::new(last_upload_success.clone()))?;
+
+        Ok(Self {
+            registry,
+            scrape_attempts,
+            scrape_failures,
+            scrape_duration,
+            rows_scraped,
+            last_scrape_success,
+            partitions_changed,
+            upload_queue_depth,
+            uploads,
+            upload_failures,
+            upload_dead_letters,
+            bytes_uploaded,
+            last_upload_success,
+        })
+    }
+
+    /// Render all metrics in the Prometheus text exposition format.
+    pub fn encode(&self) -> String {
+        let mut buffer = Vec::new();
+        if let Err(e) = TextEncoder::new().encode(&self.registry.gather(), &mut buffer) {
+            tracing::error!("Failed to encode metrics: {:?}", e);
+        }
+        String::from_utf8(buffer).unwrap_or_default()
+    }
+}
@@ -0,0 +1,100 @@
+use std::collections::HashMap;
+use std::time::Duration;
+use tokio::sync::Mutex;
+use tokio::time::{sleep, Instant};
+
+use crate::config::ScraperConfig;
+
+/// Requests per minute for hosts that are limited unless `rate_limits` overrides them
+const DEFAULT_LIMITS: &[(&str, u32)] = &[
+    // ENTSO-E allows 400 requests per minute per user
+    ("web-api.tp.entsoe.eu", 400),
+    // No published quota, kept conservative
+    ("transparency.apg.at", 120),
+];
+
+/// Token bucket that never lets more than `per_minute` requests through in any minute:
+/// a burst of a tenth of the quota plus a steady refill of the rest.
+struct TokenBucket {
+    capacity: f64,
+    tokens: f64,
+    refill_per_sec: f64,
+    last_refill: Instant,
+}
+
+impl TokenBucket {
+    fn new(per_minute: u32) -> Self {
+        let capacity = (per_minute / 10).max(1) as f64;
+        let refill_per_minute = (per_minute as f64 - capacity).max(1.0);
+        Self {
+            capacity,
+            tokens: capacity,
+            refill_per_sec: refill_per_minute / 60.0,
+            last_refill: Instant::now(),
+        }
+    }
+
+    /// Take a token, or return how long until the next one is available.
+    fn try_take(&mut self, now: Instant) -> Result<(), Duration> {
+        let elapsed = now.duration_since(self.last_refill).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.refill_per_sec).min(self.capacity);
+        self.last_refill = now;
+
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            Ok(())
+        } else {
+            Err(Duration::from_secs_f64((1.0 - self.tokens) / self.refill_per_sec))
+        }
+    }
+}
+
+/// Per-host request limits shared by all scrapers that talk to the same API.
+pub struct HostRateLimiter {
+    limits: HashMap<String, u32>,
+    buckets: Mutex<HashMap<String, TokenBucket>>,
+}
+
+impl HostRateLimiter {
+    /// Default limits, with `overrides` (host to requests per minute) taking precedence.
+    pub fn new(overrides: &HashMap<String, u32>) -> Self {
+        let mut limits: HashMap<String, u32> = DEFAULT_LIMITS.iter()
+            .map(|(host, per_minute)| (host.to_string(), *per_minute))
+            .collect();
+        limits.extend(overrides.iter().map(|(host, per_minute)| (host.clone(), *per_minute)));
+
+        Self {
+            limits,
+            buckets: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn limit_for(&self, host: &str) -> Option<u32> {
+        self.limits.get(host).copied()
+    }
+
+    /// Wait until another request to `host` is allowed. Hosts without a limit pass straight through.
+    pub async fn acquire(&self, host: &str) {
+        let Some(per_minute) = self.limit_for(host) else {
+            return;
+        };
+
+        loop {
+            let wait = {
+                let mut buckets = self.buckets.lock().await;
+                let bucket = buckets.entry(host.to_string()).or_insert_with(|| TokenBucket::new(per_minute));
+                match bucket.try_take(Instant::now()) {
+                    Ok(()) => return,
+                    Err(wait) => wait,
+                }
+            };
+            sleep(wait).await;
+        }
+    }
+}
+
+/// Host of the scraper's `url` value, which its requests are limited by.
+pub fn scraper_host(config: &ScraperConfig) -> Option<String> {
+    let url = config.scraper_config.values.get("url")?.as_str()?;
+    reqwest::Url::parse(url).ok()?.host_str().map(str::to_string)
+}
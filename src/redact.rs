@@ -0,0 +1,116 @@
+use serde_json::Value;
+use std::collections::{BTreeMap, HashMap};
+use std::io::{self, Write};
+use std::sync::RwLock;
+use tracing_subscriber::fmt::MakeWriter;
+
+pub const REDACTED: &str = "***";
+
+/// Config keys whose values are always treated as secrets
+const DEFAULT_SECRET_KEYS: &[&str] = &["token", "password", "api_key", "secret", "access_key", "secret_key"];
+
+/// Secrets shorter than this are not scrubbed from text, as they would match too much
+const MIN_SECRET_LEN: usize = 4;
+
+static EXTRA_SECRET_KEYS: RwLock<Vec<String>> = RwLock::new(Vec::new());
+static SECRET_VALUES: RwLock<Vec<String>> = RwLock::new(Vec::new());
+
+/// Add config keys (from `redact_keys` in config.json) to the default secret keys.
+pub fn add_secret_keys(keys: &[String]) {
+    let mut extra = EXTRA_SECRET_KEYS.write().unwrap_or_else(|e| e.into_inner());
+    extra.extend(keys.iter().map(|k| k.to_ascii_lowercase()));
+}
+
+/// Whether the value of config key `key` must not be shown, e.g. `token` or `entsoe_api_key`.
+pub fn is_secret_key(key: &str) -> bool {
+    let key = key.to_ascii_lowercase();
+    let matches = |secret: &str| key == secret || key.ends_with(&format!("_{}", secret));
+
+    DEFAULT_SECRET_KEYS.iter().any(|secret| matches(secret))
+        || EXTRA_SECRET_KEYS.read().unwrap_or_else(|e| e.into_inner()).iter().any(|secret| matches(secret))
+}
+
+/// Remember the secrets inside `values` so they can be scrubbed from log lines and errors.
+pub fn register_secrets(values: &HashMap<String, Value>) {
+    for (key, value) in values {
+        if is_secret_key(key) {
+            register_secret(value);
+        }
+    }
+}
+
+/// Remember every string inside `value` as a secret, e.g. a webhook URL.
+pub fn register_secret(value: &Value) {
+    let mut secrets = SECRET_VALUES.write().unwrap_or_else(|e| e.into_inner());
+    collect_strings(value, &mut secrets);
+    // Replace longer secrets first so one that contains another is not left half-visible
+    secrets.sort_by_key(|s| std::cmp::Reverse(s.len()));
+    secrets.dedup();
+}
+
+fn collect_strings(value: &Value, out: &mut Vec<String>) {
+    match value {
+        Value::String(s) if s.len() >= MIN_SECRET_LEN => out.push(s.clone()),
+        Value::Array(items) => items.iter().for_each(|item| collect_strings(item, out)),
+        Value::Object(map) => map.values().for_each(|item| collect_strings(item, out)),
+        _ => {}
+    }
+}
+
+/// Replace every registered secret in `text` with `***`.
+pub fn scrub(text: &str) -> String {
+    let secrets = SECRET_VALUES.read().unwrap_or_else(|e| e.into_inner());
+    let mut scrubbed = text.to_string();
+    for secret in secrets.iter() {
+        if scrubbed.contains(secret.as_str()) {
+            scrubbed = scrubbed.replace(secret.as_str(), REDACTED);
+        }
+    }
+    scrubbed
+}
+
+/// Copy of scraper values with secret keys replaced by `***`, for Debug output.
+pub fn redact_values(values: &HashMap<String, Value>) -> BTreeMap<&str, Value> {
+    values.iter()
+        .map(|(key, value)| {
+            let value = if is_secret_key(key) { Value::String(REDACTED.to_string()) } else { value.clone() };
+            (key.as_str(), value)
+        })
+        .collect()
+}
+
+/// `MakeWriter` for tracing layers that scrubs registered secrets from every log line.
+pub struct RedactingMakeWriter<M> {
+    inner: M,
+}
+
+impl<M> RedactingMakeWriter<M> {
+    pub fn new(inner: M) -> Self {
+        Self { inner }
+    }
+}
+
+impl<'a, M: MakeWriter<'a>> MakeWriter<'a> for RedactingMakeWriter<M> {
+    type Writer = RedactingWriter<M::Writer>;
+
+    fn make_writer(&'a self) -> Self::Writer {
+        RedactingWriter { inner: self.inner.make_writer() }
+    }
+}
+
+pub struct RedactingWriter<W> {
+    inner: W,
+}
+
+impl<W: Write> Write for RedactingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        // Formatted events are written in one call, so each buffer holds whole log lines
+        let text = String::from_utf8_lossy(buf);
+        self.inner.write_all(scrub(&text).as_bytes())?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
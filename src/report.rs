@@ -0,0 +1,70 @@
+use anyhow::{bail, Result};
+use serde::Serialize;
+use tracing_subscriber::fmt::writer::BoxMakeWriter;
+
+use crate::cli::{self, Args};
+
+/// Every day checked out or was processed, and all files were uploaded
+pub const EXIT_OK: i32 = 0;
+/// Usage or config error, or a check that could not be completed
+pub const EXIT_ERROR: i32 = 1;
+/// Missing or stale days in S3, or days that failed to backfill
+pub const EXIT_GAPS: i32 = 2;
+/// All days were backfilled but some files could not be uploaded
+pub const EXIT_UPLOAD_FAILED: i32 = 3;
+
+/// How `backfill` and `verify-uploads` print their results.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum OutputFormat {
+    /// Progress bars and a human summary
+    #[default]
+    Text,
+    /// One JSON document on stdout
+    Json,
+    /// One CSV row per scraper and day on stdout
+    Csv,
+}
+
+impl OutputFormat {
+    pub fn parse(value: &str) -> Result<Self> {
+        match value {
+            "text" => Ok(Self::Text),
+            "json" => Ok(Self::Json),
+            "csv" => Ok(Self::Csv),
+            _ => bail!("Unknown format '{}', expected text, json or csv", value),
+        }
+    }
+
+    /// The `--format` option, text when absent.
+    pub fn from_args(args: &Args) -> Result<Self> {
+        args.value(cli::FORMAT.name).map_or(Ok(Self::Text), Self::parse)
+    }
+
+    pub fn is_text(self) -> bool {
+        self == Self::Text
+    }
+
+    /// Log to stdout for text output, and to stderr when stdout carries a report.
+    pub fn log_writer(self) -> BoxMakeWriter {
+        if self.is_text() {
+            BoxMakeWriter::new(std::io::stdout)
+        } else {
+            BoxMakeWriter::new(std::io::stderr)
+        }
+    }
+}
+
+pub fn print_json<T: Serialize>(report: &T) -> Result<()> {
+    println!("{}", serde_json::to_string_pretty(report)?);
+    Ok(())
+}
+
+/// Print `rows` as CSV with a header line. Rows must be flat structs.
+pub fn print_csv<T: Serialize>(rows: impl IntoIterator<Item = T>) -> Result<()> {
+    let mut writer = csv::Writer::from_writer(std::io::stdout());
+    for row in rows {
+        writer.serialize(row)?;
+    }
+    writer.flush()?;
+    Ok(())
+}
@@ -0,0 +1,204 @@
+use anyhow::{anyhow, bail, Context, Result};
+use chrono::{DateTime, Duration, NaiveTime, TimeZone, Utc};
+use chrono_tz::Tz;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+
+use crate::config::{parse_duration, parse_timezone};
+
+/// When a scraper pool should fire tasks.
+///
+/// Either a cron expression (`sec min hour day-of-month month day-of-week`) or an
+/// interval aligned to local midnight, e.g. `{"every": "15m", "offset": "2m"}` fires
+/// at :02, :17, :32 and :47. Times are evaluated in `timezone` (default Europe/Vienna).
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct ScheduleConfig {
+    pub cron: Option<String>,
+    pub every: Option<String>,
+    pub offset: Option<String>,
+    pub timezone: Option<String>,
+}
+
+pub enum Schedule {
+    /// Fixed delay between tasks (`task_generator_delay_ms`)
+    Delay(std::time::Duration),
+    Aligned { every: Duration, offset: Duration, timezone: Tz },
+    Cron { schedule: Box<cron::Schedule>, timezone: Tz },
+}
+
+impl Schedule {
+    /// Build the schedule for a scraper, falling back to `task_generator_delay_ms`
+    /// when no `schedule` is configured.
+    pub fn from_config(config: Option<&ScheduleConfig>, delay_ms: u32) -> Result<Self> {
+        let Some(config) = config else {
+            return Ok(Schedule::Delay(std::time::Duration::from_millis(delay_ms as u64)));
+        };
+
+        let timezone = parse_timezone(config.timezone.as_deref().unwrap_or("Europe/Vienna"))?;
+
+        match (&config.cron, &config.every) {
+            (Some(expression), None) => {
+                if config.offset.is_some() {
+                    bail!("schedule.offset cannot be combined with schedule.cron");
+                }
+                let schedule = cron::Schedule::from_str(expression)
+                    .with_context(|| format!("Invalid cron expression '{}'", expression))?;
+                Ok(Schedule::Cron { schedule: Box::new(schedule), timezone })
+            }
+            (None, Some(every)) => {
+                let every = parse_duration(every)?;
+                let offset = match &config.offset {
+                    Some(offset) => parse_duration(offset)?,
+                    None => Duration::zero(),
+                };
+
+                if every <= Duration::zero() || every > Duration::days(1) {
+                    bail!("schedule.every must be between 1s and 1d");
+                }
+                if offset < Duration::zero() || offset >= every {
+                    bail!("schedule.offset must be non-negative and shorter than schedule.every");
+                }
+
+                Ok(Schedule::Aligned { every, offset, timezone })
+            }
+            (Some(_), Some(_)) => Err(anyhow!("schedule must set either cron or every, not both")),
+            (None, None) => Err(anyhow!("schedule must set either cron or every")),
+        }
+    }
+
+    /// Time to wait after `now` before the next task should fire.
+    pub fn delay_until_next(&self, now: DateTime<Utc>) -> std::time::Duration {
+        match self {
+            Schedule::Delay(delay) => *delay,
+            _ => self.next_after(now)
+                .and_then(|next| (next - now).to_std().ok())
+                .unwrap_or(std::time::Duration::from_secs(60)),
+        }
+    }
+
+    /// Next fire time strictly after `now`.
+    pub fn next_after(&self, now: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        match self {
+            Schedule::Delay(delay) => Some(now + Duration::from_std(*delay).ok()?),
+            Schedule::Cron { schedule, timezone } => {
+                schedule.after(&now.with_timezone(timezone)).next().map(|t| t.with_timezone(&Utc))
+            }
+            Schedule::Aligned { every, offset, timezone } => next_aligned(now, *every, *offset, timezone),
+        }
+    }
+}
+
+/// Next slot strictly after `now` of an aligned schedule. Slots are local wall-clock times
+/// (midnight + offset + n * every), so they keep their time of day across DST changes.
+/// Intervals that do not divide the day restart at the next local midnight. A slot skipped
+/// when the clocks go forward fires when they show the same time after the jump; a slot in
+/// the hour repeated when they go back fires at both occurrences.
+fn next_aligned(now: DateTime<Utc>, every: Duration, offset: Duration, timezone: &Tz) -> Option<DateTime<Utc>> {
+    let every_ms = every.num_milliseconds();
+    let local_now = now.with_timezone(timezone).naive_local();
+    // Start an hour early to find slots of a repeated hour
+    let from = local_now - Duration::hours(1);
+
+    let mut next: Option<DateTime<Utc>> = None;
+    let mut date = from.date();
+    while date <= local_now.date() + Duration::days(2) {
+        let midnight = date.and_time(NaiveTime::MIN);
+        let skipped_slots = ((from - midnight - offset).num_milliseconds() / every_ms).max(0);
+        let mut slot = midnight + offset + Duration::milliseconds(skipped_slots * every_ms);
+
+        while slot < midnight + Duration::days(1) {
+            // UTC offsets differ by at most an hour, so later slots can't come first
+            if next.is_some_and(|next| slot > next.with_timezone(timezone).naive_local() + Duration::hours(1)) {
+                return next;
+            }
+
+            let fires = timezone.from_local_datetime(&slot);
+            let fires = match (fires.earliest(), fires.latest()) {
+                (Some(earliest), Some(latest)) => vec![earliest, latest],
+                _ => timezone.from_local_datetime(&(slot + Duration::hours(1))).earliest().into_iter().collect(),
+            };
+            for fire in fires.into_iter().map(|fire| fire.with_timezone(&Utc)).filter(|fire| *fire > now) {
+                next = Some(next.map_or(fire, |next| next.min(fire)));
+            }
+            slot += every;
+        }
+        date += Duration::days(1);
+    }
+    next
+}
+
+impl fmt::Display for Schedule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Schedule::Delay(delay) => write!(f, "{}ms delay", delay.as_millis()),
+            Schedule::Aligned { every, offset, timezone } => {
+                write!(f, "every {}s at +{}s ({})", every.num_seconds(), offset.num_seconds(), timezone)
+            }
+            Schedule::Cron { schedule, timezone } => write!(f, "cron '{}' ({})", schedule, timezone),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn aligned(every: &str, offset: &str) -> Schedule {
+        let config = ScheduleConfig {
+            cron: None,
+            every: Some(every.to_string()),
+            offset: Some(offset.to_string()),
+            timezone: None,
+        };
+        Schedule::from_config(Some(&config), 0).unwrap()
+    }
+
+    fn utc(time: &str) -> DateTime<Utc> {
+        time.parse().unwrap()
+    }
+
+    #[test]
+    fn quarter_hours_with_offset() {
+        let schedule = aligned("15m", "2m");
+        // 11:05 in Vienna (CET)
+        assert_eq!(schedule.next_after(utc("2025-01-15T10:05:00Z")), Some(utc("2025-01-15T10:17:00Z")));
+        assert_eq!(schedule.next_after(utc("2025-01-15T10:17:00Z")), Some(utc("2025-01-15T10:32:00Z")));
+        assert_eq!(schedule.next_after(utc("2025-01-15T10:40:00Z")), Some(utc("2025-01-15T10:47:00Z")));
+        assert_eq!(schedule.next_after(utc("2025-01-15T10:47:00Z")), Some(utc("2025-01-15T11:02:00Z")));
+        // 23:50 local, the next slot is 00:02 local
+        assert_eq!(schedule.next_after(utc("2025-01-15T22:50:00Z")), Some(utc("2025-01-15T23:02:00Z")));
+    }
+
+    #[test]
+    fn daily_slot_keeps_its_local_time_across_dst() {
+        let schedule = aligned("1d", "14h");
+        // Clocks go forward on 2025-03-30, 14:00 CEST is 12:00 UTC
+        assert_eq!(schedule.next_after(utc("2025-03-29T23:30:00Z")), Some(utc("2025-03-30T12:00:00Z")));
+        // Clocks go back on 2025-10-26, 14:00 CET is 13:00 UTC
+        assert_eq!(schedule.next_after(utc("2025-10-25T22:30:00Z")), Some(utc("2025-10-26T13:00:00Z")));
+    }
+
+    #[test]
+    fn hourly_slot_skipped_by_dst_fires_after_the_jump() {
+        let schedule = aligned("1h", "0s");
+        // 01:30 CET, 02:00 doesn't exist and 03:00 CEST follows
+        assert_eq!(schedule.next_after(utc("2025-03-30T00:30:00Z")), Some(utc("2025-03-30T01:00:00Z")));
+        assert_eq!(schedule.next_after(utc("2025-03-30T01:00:00Z")), Some(utc("2025-03-30T02:00:00Z")));
+    }
+
+    #[test]
+    fn repeated_hour_fires_twice() {
+        let schedule = aligned("15m", "0s");
+        // 02:50 CEST, then 02:00 CET
+        assert_eq!(schedule.next_after(utc("2025-10-26T00:50:00Z")), Some(utc("2025-10-26T01:00:00Z")));
+        assert_eq!(schedule.next_after(utc("2025-10-26T01:50:00Z")), Some(utc("2025-10-26T02:00:00Z")));
+    }
+
+    #[test]
+    fn intervals_restart_at_midnight() {
+        let schedule = aligned("7h", "0s");
+        // 22:00 local, the slots are 00:00, 07:00, 14:00 and 21:00
+        assert_eq!(schedule.next_after(utc("2025-01-15T21:00:00Z")), Some(utc("2025-01-15T23:00:00Z")));
+    }
+}
@@ -4,16 +4,86 @@ use ve_energy_scrapers::apg_information_scraper::APGInformationScraper;
 use ve_energy_scrapers::entsoe_information_scraper::EntsoeInformationScraper;
 use ve_energy_scrapers::models::strategy_information_scraper_config::StrategyInformationScraperConfig;
 
-pub fn create_scraper(config: &StrategyInformationScraperConfig) -> Result<Box<dyn Scraper>> {
-    if let Some(url) = config.values.get("url").and_then(|v| v.as_str()) {
-        if url.contains("entsoe") {
-            Ok(Box::new(EntsoeInformationScraper::new(config.clone())?))
-        } else if url.contains("apg") {
-            Ok(Box::new(APGInformationScraper::new(config.clone())?))
-        } else {
-            Err(anyhow::anyhow!("Unknown scraper URL type: {}", url))
-        }
+use crate::config::ScraperConfig;
+
+type Constructor = fn(StrategyInformationScraperConfig) -> Result<Box<dyn Scraper>>;
+
+/// Scraper kinds that can be named in the `kind` field of a scraper config
+const SCRAPER_KINDS: &[(&str, Constructor)] = &[
+    ("apg", |config| Ok(Box::new(APGInformationScraper::new(config)?))),
+    ("entsoe", |config| Ok(Box::new(EntsoeInformationScraper::new(config)?))),
+];
+
+pub fn known_kinds() -> Vec<&'static str> {
+    SCRAPER_KINDS.iter().map(|(kind, _)| *kind).collect()
+}
+
+/// Resolve the scraper kind, preferring the explicit `kind` field and falling back
+/// to guessing from the URL for configs that predate it.
+pub fn resolve_kind(config: &ScraperConfig) -> Result<&'static str> {
+    let name = &config.scraper_config.name;
+
+    if let Some(kind) = &config.kind {
+        return SCRAPER_KINDS.iter()
+            .find(|(known, _)| known.eq_ignore_ascii_case(kind))
+            .map(|(known, _)| *known)
+            .ok_or_else(|| anyhow::anyhow!(
+                "Unknown scraper kind '{}' for {}, known kinds: {}", kind, name, known_kinds().join(", ")
+            ));
+    }
+
+    let url = config.scraper_config.values.get("url").and_then(|v| v.as_str())
+        .ok_or_else(|| anyhow::anyhow!(
+            "No kind and no URL in config for {}, set kind to one of: {}", name, known_kinds().join(", ")
+        ))?;
+
+    if url.contains("entsoe") {
+        Ok("entsoe")
+    } else if url.contains("apg") {
+        Ok("apg")
     } else {
-        Err(anyhow::anyhow!("Missing URL in config for {}", config.name))
+        Err(anyhow::anyhow!(
+            "Cannot infer scraper kind from URL {} for {}, set kind to one of: {}", url, name, known_kinds().join(", ")
+        ))
     }
 }
+
+/// Config values a scraper of `kind` cannot run without that are absent from `config`.
+pub fn missing_keys(kind: &str, config: &StrategyInformationScraperConfig) -> Vec<&'static str> {
+    let has = |key: &str| config.values.get(key).is_some_and(|v| !v.is_null());
+    let is_balancing_bids = config.values.get("is_balancing_bids")
+        .is_some_and(|v| v.as_bool() == Some(true) || v.as_str() == Some("true"));
+
+    let mut missing = Vec::new();
+    match kind {
+        "apg" => {
+            if !has("url") {
+                missing.push("url");
+            }
+            if !has("url_template") {
+                missing.push("url_template");
+            }
+            if !is_balancing_bids && !has("value_column") && !has("value_columns") {
+                missing.push("value_column or value_columns");
+            }
+        }
+        "entsoe" => {
+            if !has("url") {
+                missing.push("url");
+            }
+            if !has("token") {
+                missing.push("token");
+            }
+        }
+        _ => {}
+    }
+    missing
+}
+
+pub fn create_scraper(config: &ScraperConfig) -> Result<Box<dyn Scraper>> {
+    let kind = resolve_kind(config)?;
+    let (_, constructor) = SCRAPER_KINDS.iter()
+        .find(|(known, _)| *known == kind)
+        .expect("resolve_kind only returns registered kinds");
+    constructor(config.scraper_config.clone())
+}
@@ -1,116 +1,226 @@
 use anyhow::Result;
-use chrono::{DateTime, Utc, Datelike, TimeZone};
+use chrono::{DateTime, NaiveDate, Utc, Datelike, TimeZone};
 use chrono_tz::Europe::Vienna;
 use std::fs::File;
 use std::path::Path;
 use std::collections::{HashSet, HashMap};
+use serde::Serialize;
 use std::sync::Arc;
 use tokio::sync::Mutex;
-use tracing::info;
+use tracing::{info, warn};
 
-use arrow::array::{Float64Array, TimestampMicrosecondArray, Array, Int32Array, StringArray};
+use arrow::array::{Float64Array, TimestampMicrosecondArray, Array, BooleanArray, Int32Array, StringArray};
+use arrow::compute::filter_record_batch;
 use arrow::datatypes::{DataType, Field, Schema, TimeUnit};
 use arrow::record_batch::RecordBatch;
 use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
 use parquet::arrow::ArrowWriter;
 use ve_energy_scrapers::models::scraper_data::{ScraperData, ScraperPayload, Bid};
 
+use crate::metrics::metrics;
+use crate::upload_queue::UploadQueue;
+use crate::window::local_day_start;
+
+type ValueRow = (DateTime<Utc>, DateTime<Utc>, HashMap<String, f64>);
+type BidRow = (DateTime<Utc>, DateTime<Utc>, Bid);
+/// start, end, bid type, direction and rank of a stored bid
+type BidKey = (i64, i64, String, String, i32);
+
+/// How a backfill treats rows that are already stored.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SaveMode {
+    /// Write rows that are missing or were backfilled before; rows from live scrapes
+    /// keep their values and only get missing ones added
+    #[default]
+    Fill,
+    /// Replace stored values of the scraped periods with the scraped ones, which then
+    /// count as backfilled
+    Overwrite,
+}
+
+/// How scraped rows are merged into a partition.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Merge {
+    /// Changed rows are updated (values) or appended (bids) with `scraped_at` set to now
+    Live,
+    /// Rows keep a null `scraped_at`
+    Backfill(SaveMode),
+}
+
+/// Difference between scraped rows and what is stored, see `Storage::compare`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize)]
+pub struct PartitionDiff {
+    /// Scraped rows (values) or bids with no stored counterpart
+    pub rows_added: usize,
+    /// Stored values that the scraped rows would change, or add to an existing row
+    pub values_changed: usize,
+    /// Largest absolute difference between a stored and a scraped value
+    pub max_abs_delta: f64,
+}
+
+impl PartitionDiff {
+    fn record_change(&mut self, stored: Option<f64>, scraped: Option<f64>) {
+        match (stored, scraped) {
+            (Some(stored), Some(scraped)) if (stored - scraped).abs() > f64::EPSILON => {
+                self.values_changed += 1;
+                self.max_abs_delta = self.max_abs_delta.max((stored - scraped).abs());
+            }
+            (None, Some(_)) | (Some(_), None) => self.values_changed += 1,
+            _ => {}
+        }
+    }
+}
+
+/// How much of a local day is stored, see `Storage::partition_state`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PartitionState {
+    Missing,
+    /// Present, but its rows leave part of the day uncovered or the file can't be read
+    Incomplete,
+    Complete,
+}
+
+/// Local data directory unless `--data-dir` says otherwise
+pub const DEFAULT_DATA_DIR: &str = "data";
+
 pub struct Storage {
     base_path: String,
-    dirty_files: Option<Arc<Mutex<HashSet<String>>>>,
+    dirty_files: Option<Arc<UploadQueue>>,
+    /// One lock per partition file, serializing the read-modify-write of saves that share it
+    partition_locks: Mutex<HashMap<String, Arc<Mutex<()>>>>,
 }
 
 impl Storage {
-    pub fn new(base_path: &str, dirty_files: Option<Arc<Mutex<HashSet<String>>>>) -> Self {
+    pub fn new(base_path: &str, dirty_files: Option<Arc<UploadQueue>>) -> Self {
         Self {
             base_path: base_path.to_string(),
             dirty_files,
+            partition_locks: Mutex::new(HashMap::new()),
         }
     }
 
     pub async fn save_if_new(&self, name: &str, subfolder: Option<&str>, data: &[ScraperData]) -> Result<bool> {
-        self.save_with_scraped_at(name, subfolder, data, true).await
+        self.save_with_merge(name, subfolder, data, Merge::Live).await
     }
 
-    pub async fn save_backfill(&self, name: &str, subfolder: Option<&str>, data: &[ScraperData]) -> Result<bool> {
-        self.save_with_scraped_at(name, subfolder, data, false).await
+    pub async fn save_backfill(&self, name: &str, subfolder: Option<&str>, data: &[ScraperData], mode: SaveMode) -> Result<bool> {
+        self.save_with_merge(name, subfolder, data, Merge::Backfill(mode)).await
     }
 
-    async fn save_with_scraped_at(&self, name: &str, subfolder: Option<&str>, data: &[ScraperData], set_scraped_at: bool) -> Result<bool> {
+    async fn save_with_merge(&self, name: &str, subfolder: Option<&str>, data: &[ScraperData], merge: Merge) -> Result<bool> {
         let mut saved_any = false;
-        
-        // Separate data by type
-        let mut values_data: Vec<(DateTime<Utc>, DateTime<Utc>, HashMap<String, f64>)> = Vec::new();
-        let mut bids_data: Vec<(DateTime<Utc>, DateTime<Utc>, Bid)> = Vec::new();
-
-        for item in data {
-            match &item.payload {
-                ScraperPayload::Values(map) => {
-                    values_data.push((item.delivery_from, item.delivery_to, map.clone()));
-                }
-                ScraperPayload::Bids(bids) => {
-                    for bid in bids {
-                        bids_data.push((item.delivery_from, item.delivery_to, bid.clone()));
-                    }
+        let (values_data, bids_data) = split_payloads(data);
+
+        for (file_path, group_data) in self.group_by_partition(name, subfolder, values_data) {
+            let changed = {
+                let lock = self.partition_lock(&file_path).await;
+                let _guard = lock.lock().await;
+                let path = file_path.clone();
+                tokio::task::spawn_blocking(move || Self::process_values_partition(&path, &group_data, merge)).await??
+            };
+            if changed {
+                saved_any = true;
+                metrics().partitions_changed.with_label_values(&[name]).inc();
+                if let Some(dirty) = &self.dirty_files {
+                    dirty.insert(file_path).await?;
                 }
             }
         }
 
-        if !values_data.is_empty() {
-            let mut groups: HashMap<(i32, u32, u32), Vec<(DateTime<Utc>, DateTime<Utc>, HashMap<String, f64>)>> = HashMap::new();
-            for (start, end, map) in values_data {
-                let start_cet = start.with_timezone(&Vienna);
-                let year = start_cet.year();
-                let month = start_cet.month();
-                let day = start_cet.day();
-                groups.entry((year, month, day)).or_default().push((start, end, map));
+        for (file_path, group_data) in self.group_by_partition(name, subfolder, bids_data) {
+            let changed = {
+                let lock = self.partition_lock(&file_path).await;
+                let _guard = lock.lock().await;
+                let path = file_path.clone();
+                tokio::task::spawn_blocking(move || Self::process_bids_partition(&path, &group_data, merge)).await??
+            };
+            if changed {
+                saved_any = true;
+                metrics().partitions_changed.with_label_values(&[name]).inc();
+                if let Some(dirty) = &self.dirty_files {
+                    dirty.insert(file_path).await?;
+                }
             }
+        }
 
-            for ((year, month, day), group_data) in groups {
-                let folder_path = if let Some(sub) = subfolder {
-                    format!("{}/{}", self.base_path, sub)
-                } else {
-                    format!("{}/{}", self.base_path, name)
-                };
-
-                let file_path = format!("{}/year={}/month={:02}/day={:02}/data.parquet", folder_path, year, month, day);
-                if self.process_values_partition(&file_path, &group_data, set_scraped_at)? {
-                    saved_any = true;
-                    if let Some(dirty) = &self.dirty_files {
-                        dirty.lock().await.insert(file_path);
+        Ok(saved_any)
+    }
+
+    /// Lock of a partition file, shared by every save that writes it at the same time.
+    async fn partition_lock(&self, file_path: &str) -> Arc<Mutex<()>> {
+        let mut locks = self.partition_locks.lock().await;
+        // Forget the locks of partitions nobody is writing
+        locks.retain(|_, lock| Arc::strong_count(lock) > 1);
+        locks.entry(file_path.to_string()).or_default().clone()
+    }
+
+    /// What saving `data` with `SaveMode::Overwrite` would change, without writing anything.
+    /// The stored partitions are read on the blocking pool.
+    pub async fn compare(&self, name: &str, subfolder: Option<&str>, data: &[ScraperData]) -> Result<PartitionDiff> {
+        let mut diff = PartitionDiff::default();
+        let (values_data, bids_data) = split_payloads(data);
+
+        for (file_path, group_data) in self.group_by_partition(name, subfolder, values_data) {
+            let (stored, _) = tokio::task::spawn_blocking(move || read_values(Path::new(&file_path))).await??;
+            for (start, end, new_values) in group_data {
+                match stored.get(&(start.timestamp_micros(), end.timestamp_micros())) {
+                    Some((_, stored_values)) => {
+                        for (k, v) in &new_values {
+                            diff.record_change(stored_values.get(k).copied(), Some(*v));
+                        }
                     }
+                    None => diff.rows_added += 1,
                 }
             }
         }
 
-        if !bids_data.is_empty() {
-             let mut groups: HashMap<(i32, u32, u32), Vec<(DateTime<Utc>, DateTime<Utc>, Bid)>> = HashMap::new();
-            for (start, end, bid) in bids_data {
-                let start_cet = start.with_timezone(&Vienna);
-                let year = start_cet.year();
-                let month = start_cet.month();
-                let day = start_cet.day();
-                groups.entry((year, month, day)).or_default().push((start, end, bid));
-            }
-
-            for ((year, month, day), group_data) in groups {
-                let folder_path = if let Some(sub) = subfolder {
-                    format!("{}/{}", self.base_path, sub)
-                } else {
-                    format!("{}/{}", self.base_path, name)
-                };
-
-                let file_path = format!("{}/year={}/month={:02}/day={:02}/data.parquet", folder_path, year, month, day);
-                if self.process_bids_partition(&file_path, &group_data, set_scraped_at)? {
-                    saved_any = true;
-                    if let Some(dirty) = &self.dirty_files {
-                        dirty.lock().await.insert(file_path);
+        for (file_path, group_data) in self.group_by_partition(name, subfolder, bids_data) {
+            let (stored, _) = tokio::task::spawn_blocking(move || read_bids(Path::new(&file_path))).await??;
+            for (start, end, bid) in group_data {
+                match stored.get(&bid_key(start, end, &bid)) {
+                    Some((price, volume, _)) => {
+                        diff.record_change(*price, bid.price);
+                        diff.record_change(*volume, bid.volume);
                     }
+                    None => diff.rows_added += 1,
                 }
             }
         }
 
-        Ok(saved_any)
+        Ok(diff)
+    }
+
+    /// Rows grouped by the partition file of their local (Europe/Vienna) delivery day.
+    fn group_by_partition<T>(&self, name: &str, subfolder: Option<&str>, rows: Vec<(DateTime<Utc>, DateTime<Utc>, T)>) -> HashMap<String, Vec<(DateTime<Utc>, DateTime<Utc>, T)>> {
+        let mut groups: HashMap<String, Vec<(DateTime<Utc>, DateTime<Utc>, T)>> = HashMap::new();
+        for (start, end, row) in rows {
+            let start_cet = start.with_timezone(&Vienna);
+            let file_path = self.partition_file(name, subfolder, start_cet.year(), start_cet.month(), start_cet.day());
+            groups.entry(file_path).or_default().push((start, end, row));
+        }
+        groups
+    }
+
+    /// Partition file holding the local (Europe/Vienna) day `date` of a scraper.
+    pub fn partition_path(&self, name: &str, subfolder: Option<&str>, date: NaiveDate) -> String {
+        self.partition_file(name, subfolder, date.year(), date.month(), date.day())
+    }
+
+    fn partition_file(&self, name: &str, subfolder: Option<&str>, year: i32, month: u32, day: u32) -> String {
+        let folder_path = if let Some(sub) = subfolder {
+            format!("{}/{}", self.base_path, sub)
+        } else {
+            format!("{}/{}", self.base_path, name)
+        };
+
+        format!("{}/year={}/month={:02}/day={:02}/data.parquet", folder_path, year, month, day)
+    }
+
+    /// Whether the stored rows of `date` cover it from its local midnight to the next one
+    /// without gaps. The partition is read on the blocking pool.
+    pub async fn partition_state(&self, name: &str, subfolder: Option<&str>, date: NaiveDate) -> Result<PartitionState> {
+        let file_path = self.partition_path(name, subfolder, date);
+        Ok(tokio::task::spawn_blocking(move || partition_file_state(&file_path, date)).await?)
     }
 
     pub async fn cleanup(&self, retention_days: u64) -> Result<()> {
@@ -168,7 +278,7 @@ impl Storage {
             .and_then(|s| s.parse().ok())
     }
 
-    fn process_values_partition(&self, file_path: &str, data: &[(DateTime<Utc>, DateTime<Utc>, HashMap<String, f64>)], set_scraped_at: bool) -> Result<bool> {
+    fn process_values_partition(file_path: &str, data: &[ValueRow], merge: Merge) -> Result<bool> {
         let path = Path::new(file_path);
 
         // Create directory if it doesn't exist
@@ -176,54 +286,9 @@ impl Storage {
             std::fs::create_dir_all(parent)?;
         }
 
-        let mut all_rows: HashMap<(i64, i64), (i64, HashMap<String, f64>)> = HashMap::new();
-        let mut all_columns: HashSet<String> = HashSet::new();
-
-        if path.exists() {
-            let file = File::open(path)?;
-            let builder = ParquetRecordBatchReaderBuilder::try_new(file)?;
-            let mut reader = builder.build()?;
-            
-            while let Some(batch) = reader.next() {
-                let batch = batch?;
-                let schema = batch.schema();
-                
-                let start_col = batch.column(0).as_any().downcast_ref::<TimestampMicrosecondArray>().unwrap();
-                let end_col = batch.column(1).as_any().downcast_ref::<TimestampMicrosecondArray>().unwrap();
-                let scraped_at_idx = schema.index_of("scraped_at").ok();
-                let scraped_at_col = if let Some(idx) = scraped_at_idx {
-                    Some(batch.column(idx).as_any().downcast_ref::<TimestampMicrosecondArray>().unwrap())
-                } else {
-                    None
-                };
-
-                // Identify value columns
-                let mut value_cols = Vec::new();
-                for (i, field) in schema.fields().iter().enumerate() {
-                    let name = field.name();
-                    if name != "start" && name != "end" && name != "scraped_at" {
-                        all_columns.insert(name.clone());
-                        value_cols.push((name.clone(), batch.column(i).as_any().downcast_ref::<Float64Array>().unwrap()));
-                    }
-                }
-
-                for i in 0..start_col.len() {
-                    let start = start_col.value(i);
-                    let end = end_col.value(i);
-                    let scraped_at = scraped_at_col.map(|c| c.value(i)).unwrap_or(0);
-                    
-                    let entry = all_rows.entry((start, end)).or_insert((scraped_at, HashMap::new()));
-                    
-                    for (name, col) in &value_cols {
-                        if !col.is_null(i) {
-                            entry.1.insert(name.clone(), col.value(i));
-                        }
-                    }
-                }
-            }
-        }
+        let (mut all_rows, mut all_columns) = read_values(path)?;
 
-        let now_micros = if set_scraped_at {
+        let now_micros = if merge == Merge::Live {
             Utc::now().timestamp_micros()
         } else {
             0 // null for backfilled data
@@ -241,27 +306,26 @@ impl Storage {
             let entry = all_rows.entry((start_micros, end_micros)).or_insert((0, HashMap::new()));
             let (existing_scraped_at, existing_values) = entry;
 
-            let mut changed = false;
-            if *existing_scraped_at == 0 {
-                changed = true;
-            } else {
-                for (k, v) in new_values {
-                    match existing_values.get(k) {
-                        Some(old_v) => {
-                            if (old_v - v).abs() > f64::EPSILON {
-                                changed = true;
-                            }
-                        }
-                        None => changed = true,
-                    }
-                }
-            }
+            // Rows with a scraped_at come from live scrapes
+            let is_live = *existing_scraped_at != 0;
+            let changed = match merge {
+                Merge::Live => !is_live || values_differ(existing_values, new_values),
+                Merge::Backfill(SaveMode::Fill) if is_live => new_values.keys().any(|k| !existing_values.contains_key(k)),
+                Merge::Backfill(_) => values_differ(existing_values, new_values),
+            };
 
             if changed {
                 has_changes = true;
-                *existing_scraped_at = now_micros;
-                for (k, v) in new_values {
-                    existing_values.insert(k.clone(), *v);
+                if merge == Merge::Backfill(SaveMode::Fill) && is_live {
+                    // Keep the live values and when they were scraped
+                    for (k, v) in new_values {
+                        existing_values.entry(k.clone()).or_insert(*v);
+                    }
+                } else {
+                    *existing_scraped_at = now_micros;
+                    for (k, v) in new_values {
+                        existing_values.insert(k.clone(), *v);
+                    }
                 }
             }
         }
@@ -331,7 +395,7 @@ impl Storage {
         Ok(true)
     }
 
-    fn process_bids_partition(&self, file_path: &str, data: &[(DateTime<Utc>, DateTime<Utc>, Bid)], set_scraped_at: bool) -> Result<bool> {
+    fn process_bids_partition(file_path: &str, data: &[BidRow], merge: Merge) -> Result<bool> {
         let path = Path::new(file_path);
 
         // Create directory if it doesn't exist
@@ -339,8 +403,9 @@ impl Storage {
             std::fs::create_dir_all(parent)?;
         }
 
-        let mut latest_values: HashMap<(i64, i64, String, String, i32), (Option<f64>, Option<f64>)> = HashMap::new();
-        let mut existing_batches = Vec::new();
+        let (mut latest_values, existing_batches) = read_bids(path)?;
+        // Bids replaced by `SaveMode::Overwrite`, dropped from the existing batches
+        let mut replaced: HashSet<BidKey> = HashSet::new();
         
         // Define the target schema
         let schema = Arc::new(Schema::new(vec![
@@ -354,38 +419,6 @@ impl Storage {
             Field::new("scraped_at", DataType::Timestamp(TimeUnit::Microsecond, Some("UTC".into())), true),
         ]));
 
-        if path.exists() {
-            let file = File::open(path)?;
-            let builder = ParquetRecordBatchReaderBuilder::try_new(file)?;
-            let mut reader = builder.build()?;
-            
-            while let Some(batch) = reader.next() {
-                let batch = batch?;
-                
-                // Extract data for deduplication
-                let start_col = batch.column(0).as_any().downcast_ref::<TimestampMicrosecondArray>().unwrap();
-                let end_col = batch.column(1).as_any().downcast_ref::<TimestampMicrosecondArray>().unwrap();
-                let bid_type_col = batch.column(2).as_any().downcast_ref::<StringArray>().unwrap();
-                let direction_col = batch.column(3).as_any().downcast_ref::<StringArray>().unwrap();
-                let rank_col = batch.column(4).as_any().downcast_ref::<Int32Array>().unwrap();
-                let price_col = batch.column(5).as_any().downcast_ref::<Float64Array>().unwrap();
-                let volume_col = batch.column(6).as_any().downcast_ref::<Float64Array>().unwrap();
-                
-                for i in 0..start_col.len() {
-                    let start = start_col.value(i);
-                    let end = end_col.value(i);
-                    let bid_type = bid_type_col.value(i).to_string();
-                    let direction = direction_col.value(i).to_string();
-                    let rank = rank_col.value(i);
-                    let price = if price_col.is_null(i) { None } else { Some(price_col.value(i)) };
-                    let volume = if volume_col.is_null(i) { None } else { Some(volume_col.value(i)) };
-                    
-                    latest_values.insert((start, end, bid_type, direction, rank), (price, volume));
-                }
-                existing_batches.push(batch);
-            }
-        }
-
         let mut new_starts = Vec::new();
         let mut new_ends = Vec::new();
         let mut new_bid_types = Vec::new();
@@ -395,23 +428,22 @@ impl Storage {
         let mut new_volumes = Vec::new();
         let mut new_scraped_ats = Vec::new();
         
-        let now_micros = if set_scraped_at {
+        let now_micros = if merge == Merge::Live {
             Utc::now().timestamp_micros()
         } else {
             0 // null for backfilled data
         };
 
         for (start, end, bid) in data {
-            let start_micros = start.timestamp_micros();
-            let end_micros = end.timestamp_micros();
-            let bid_type = format!("{:?}", bid.bid_type);
-            let direction = format!("{:?}", bid.direction);
-            let rank = bid.rank;
+            let key = bid_key(*start, *end, bid);
+            let (start_micros, end_micros, bid_type, direction, rank) = key.clone();
             let price = bid.price;
             let volume = bid.volume;
             
-            let is_changed = match latest_values.get(&(start_micros, end_micros, bid_type.clone(), direction.clone(), rank)) {
-                Some((last_price, last_volume)) => {
+            let is_changed = match latest_values.get(&key) {
+                // Fill keeps bids whose latest row comes from a live scrape
+                Some((_, _, last_scraped_at)) if merge == Merge::Backfill(SaveMode::Fill) && *last_scraped_at != 0 => false,
+                Some((last_price, last_volume, _)) => {
                     let price_changed = match (last_price, price) {
                         (Some(lp), Some(p)) => (lp - p).abs() > f64::EPSILON,
                         (None, None) => false,
@@ -430,14 +462,18 @@ impl Storage {
             if is_changed {
                 new_starts.push(start_micros);
                 new_ends.push(end_micros);
-                new_bid_types.push(bid_type.clone());
-                new_directions.push(direction.clone());
+                new_bid_types.push(bid_type);
+                new_directions.push(direction);
                 new_ranks.push(rank);
                 new_prices.push(price);
                 new_volumes.push(volume);
                 new_scraped_ats.push(now_micros);
                 
-                latest_values.insert((start_micros, end_micros, bid_type, direction, rank), (price, volume));
+                // Live scrapes keep the history of a bid, an overwrite keeps only the new row
+                if merge == Merge::Backfill(SaveMode::Overwrite) && latest_values.contains_key(&key) {
+                    replaced.insert(key.clone());
+                }
+                latest_values.insert(key, (price, volume, now_micros));
             }
         }
 
@@ -474,6 +510,7 @@ impl Storage {
         let mut writer = ArrowWriter::try_new(file, schema.clone(), None)?;
 
         for batch in existing_batches {
+            let batch = if replaced.is_empty() { batch } else { drop_bids(&batch, &replaced)? };
             writer.write(&batch)?;
         }
         writer.write(&new_batch)?;
@@ -486,3 +523,374 @@ impl Storage {
         Ok(true)
     }
 }
+
+fn partition_file_state(file_path: &str, date: NaiveDate) -> PartitionState {
+    if !Path::new(file_path).exists() {
+        return PartitionState::Missing;
+    }
+
+    match read_intervals(file_path) {
+        Ok(intervals) => {
+            let day_start = local_day_start(date, &Vienna).timestamp_micros();
+            let day_end = local_day_start(date + chrono::Duration::days(1), &Vienna).timestamp_micros();
+            if covers(intervals, day_start, day_end) {
+                PartitionState::Complete
+            } else {
+                PartitionState::Incomplete
+            }
+        }
+        Err(e) => {
+            warn!("Failed to read {}, treating it as incomplete: {:?}", file_path, e);
+            PartitionState::Incomplete
+        }
+    }
+}
+
+/// `start` and `end` of every row of a partition file, in microseconds.
+fn read_intervals(file_path: &str) -> Result<Vec<(i64, i64)>> {
+    let file = File::open(file_path)?;
+    let reader = ParquetRecordBatchReaderBuilder::try_new(file)?.build()?;
+
+    let mut intervals = Vec::new();
+    for batch in reader {
+        let batch = batch?;
+        let start_col = batch.column(0).as_any().downcast_ref::<TimestampMicrosecondArray>()
+            .ok_or_else(|| anyhow::anyhow!("Unexpected type of the start column in {}", file_path))?;
+        let end_col = batch.column(1).as_any().downcast_ref::<TimestampMicrosecondArray>()
+            .ok_or_else(|| anyhow::anyhow!("Unexpected type of the end column in {}", file_path))?;
+
+        intervals.extend((0..start_col.len()).map(|i| (start_col.value(i), end_col.value(i))));
+    }
+
+    Ok(intervals)
+}
+
+/// Whether `intervals` cover `from..to` without a gap.
+fn covers(mut intervals: Vec<(i64, i64)>, from: i64, to: i64) -> bool {
+    intervals.sort_unstable();
+
+    let mut covered_until = from;
+    for (start, end) in intervals {
+        if start > covered_until {
+            break;
+        }
+        covered_until = covered_until.max(end);
+    }
+    covered_until >= to
+}
+
+/// Separate scraped data by type.
+fn split_payloads(data: &[ScraperData]) -> (Vec<ValueRow>, Vec<BidRow>) {
+    let mut values_data: Vec<ValueRow> = Vec::new();
+    let mut bids_data: Vec<BidRow> = Vec::new();
+
+    for item in data {
+        match &item.payload {
+            ScraperPayload::Values(map) => {
+                values_data.push((item.delivery_from, item.delivery_to, map.clone()));
+            }
+            ScraperPayload::Bids(bids) => {
+                for bid in bids {
+                    bids_data.push((item.delivery_from, item.delivery_to, bid.clone()));
+                }
+            }
+        }
+    }
+
+    (values_data, bids_data)
+}
+
+/// Whether any of the `new` values is missing from `stored` or differs from it.
+fn values_differ(stored: &HashMap<String, f64>, new: &HashMap<String, f64>) -> bool {
+    new.iter().any(|(k, v)| match stored.get(k) {
+        Some(old_v) => (old_v - v).abs() > f64::EPSILON,
+        None => true,
+    })
+}
+
+/// Stored rows of a values partition by (start, end), with their `scraped_at` (0 when null)
+/// and values, and every value column. Empty when the file doesn't exist.
+fn read_values(path: &Path) -> Result<(HashMap<(i64, i64), (i64, HashMap<String, f64>)>, HashSet<String>)> {
+    let mut all_rows: HashMap<(i64, i64), (i64, HashMap<String, f64>)> = HashMap::new();
+    let mut all_columns: HashSet<String> = HashSet::new();
+
+    if path.exists() {
+        let file = File::open(path)?;
+        let builder = ParquetRecordBatchReaderBuilder::try_new(file)?;
+        let mut reader = builder.build()?;
+        
+        while let Some(batch) = reader.next() {
+            let batch = batch?;
+            let schema = batch.schema();
+            
+            let start_col = batch.column(0).as_any().downcast_ref::<TimestampMicrosecondArray>().unwrap();
+            let end_col = batch.column(1).as_any().downcast_ref::<TimestampMicrosecondArray>().unwrap();
+            let scraped_at_idx = schema.index_of("scraped_at").ok();
+            let scraped_at_col = if let Some(idx) = scraped_at_idx {
+                Some(batch.column(idx).as_any().downcast_ref::<TimestampMicrosecondArray>().unwrap())
+            } else {
+                None
+            };
+
+            // Identify value columns
+            let mut value_cols = Vec::new();
+            for (i, field) in schema.fields().iter().enumerate() {
+                let name = field.name();
+                if name != "start" && name != "end" && name != "scraped_at" {
+                    all_columns.insert(name.clone());
+                    value_cols.push((name.clone(), batch.column(i).as_any().downcast_ref::<Float64Array>().unwrap()));
+                }
+            }
+
+            for i in 0..start_col.len() {
+                let start = start_col.value(i);
+                let end = end_col.value(i);
+                let scraped_at = scraped_at_col.map(|c| c.value(i)).unwrap_or(0);
+                
+                let entry = all_rows.entry((start, end)).or_insert((scraped_at, HashMap::new()));
+                
+                for (name, col) in &value_cols {
+                    if !col.is_null(i) {
+                        entry.1.insert(name.clone(), col.value(i));
+                    }
+                }
+            }
+        }
+    }
+
+    Ok((all_rows, all_columns))
+}
+
+fn bid_key(start: DateTime<Utc>, end: DateTime<Utc>, bid: &Bid) -> BidKey {
+    (start.timestamp_micros(), end.timestamp_micros(), format!("{:?}", bid.bid_type), format!("{:?}", bid.direction), bid.rank)
+}
+
+/// Latest price, volume and `scraped_at` (0 when null) of every stored bid, and the stored
+/// batches. Empty when the file doesn't exist.
+fn read_bids(path: &Path) -> Result<(HashMap<BidKey, (Option<f64>, Option<f64>, i64)>, Vec<RecordBatch>)> {
+    let mut latest_values: HashMap<BidKey, (Option<f64>, Option<f64>, i64)> = HashMap::new();
+    let mut existing_batches = Vec::new();
+
+    if path.exists() {
+        let file = File::open(path)?;
+        let builder = ParquetRecordBatchReaderBuilder::try_new(file)?;
+        let mut reader = builder.build()?;
+        
+        while let Some(batch) = reader.next() {
+            let batch = batch?;
+            
+            // Extract data for deduplication
+            let start_col = batch.column(0).as_any().downcast_ref::<TimestampMicrosecondArray>().unwrap();
+            let end_col = batch.column(1).as_any().downcast_ref::<TimestampMicrosecondArray>().unwrap();
+            let bid_type_col = batch.column(2).as_any().downcast_ref::<StringArray>().unwrap();
+            let direction_col = batch.column(3).as_any().downcast_ref::<StringArray>().unwrap();
+            let rank_col = batch.column(4).as_any().downcast_ref::<Int32Array>().unwrap();
+            let price_col = batch.column(5).as_any().downcast_ref::<Float64Array>().unwrap();
+            let volume_col = batch.column(6).as_any().downcast_ref::<Float64Array>().unwrap();
+            let scraped_at_col = batch.schema().index_of("scraped_at").ok()
+                .and_then(|idx| batch.column(idx).as_any().downcast_ref::<TimestampMicrosecondArray>().cloned());
+            
+            for i in 0..start_col.len() {
+                let start = start_col.value(i);
+                let end = end_col.value(i);
+                let bid_type = bid_type_col.value(i).to_string();
+                let direction = direction_col.value(i).to_string();
+                let rank = rank_col.value(i);
+                let price = if price_col.is_null(i) { None } else { Some(price_col.value(i)) };
+                let volume = if volume_col.is_null(i) { None } else { Some(volume_col.value(i)) };
+                let scraped_at = scraped_at_col.as_ref()
+                    .filter(|col| !col.is_null(i))
+                    .map(|col| col.value(i))
+                    .unwrap_or(0);
+                
+                latest_values.insert((start, end, bid_type, direction, rank), (price, volume, scraped_at));
+            }
+            existing_batches.push(batch);
+        }
+    }
+
+    Ok((latest_values, existing_batches))
+}
+
+/// `batch` without the rows of the bids in `keys`.
+fn drop_bids(batch: &RecordBatch, keys: &HashSet<BidKey>) -> Result<RecordBatch> {
+    let start_col = batch.column(0).as_any().downcast_ref::<TimestampMicrosecondArray>().unwrap();
+    let end_col = batch.column(1).as_any().downcast_ref::<TimestampMicrosecondArray>().unwrap();
+    let bid_type_col = batch.column(2).as_any().downcast_ref::<StringArray>().unwrap();
+    let direction_col = batch.column(3).as_any().downcast_ref::<StringArray>().unwrap();
+    let rank_col = batch.column(4).as_any().downcast_ref::<Int32Array>().unwrap();
+
+    let keep: BooleanArray = (0..batch.num_rows())
+        .map(|i| {
+            let key = (start_col.value(i), end_col.value(i), bid_type_col.value(i).to_string(),
+                direction_col.value(i).to_string(), rank_col.value(i));
+            Some(!keys.contains(&key))
+        })
+        .collect();
+    Ok(filter_record_batch(batch, &keep)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Duration;
+
+    /// Data directory below the system temp dir, removed on drop.
+    struct TempDataDir(std::path::PathBuf);
+
+    impl TempDataDir {
+        fn new(name: &str) -> Self {
+            let path = std::env::temp_dir().join(format!("scraping_service_{}_{}", name, std::process::id()));
+            let _ = std::fs::remove_dir_all(&path);
+            Self(path)
+        }
+
+        fn storage(&self) -> Storage {
+            Storage::new(self.0.to_str().unwrap(), None)
+        }
+    }
+
+    impl Drop for TempDataDir {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.0);
+        }
+    }
+
+    /// A `price` for every quarter hour of the local day `date` except those in `skip`.
+    fn quarter_hours(date: NaiveDate, skip: &[usize], price: f64) -> Vec<ScraperData> {
+        let day_start = local_day_start(date, &Vienna);
+        (0..96)
+            .filter(|i| !skip.contains(i))
+            .map(|i| {
+                let start = day_start + Duration::minutes(15 * i as i64);
+                ScraperData {
+                    delivery_from: start,
+                    delivery_to: start + Duration::minutes(15),
+                    payload: ScraperPayload::Values(HashMap::from([("price".to_string(), price)])),
+                }
+            })
+            .collect()
+    }
+
+    #[test]
+    fn covers_requires_contiguous_intervals() {
+        assert!(covers(vec![(10, 20), (0, 10)], 0, 20));
+        assert!(covers(vec![(-5, 12), (8, 25)], 0, 20));
+        assert!(!covers(vec![(0, 8), (12, 20)], 0, 20));
+        assert!(!covers(vec![(0, 15)], 0, 20));
+        assert!(!covers(vec![], 0, 20));
+    }
+
+    #[tokio::test]
+    async fn partition_with_a_gap_is_incomplete() {
+        let dir = TempDataDir::new("partition_state");
+        let storage = dir.storage();
+        let full = NaiveDate::from_ymd_opt(2025, 3, 10).unwrap();
+        let gap = NaiveDate::from_ymd_opt(2025, 3, 11).unwrap();
+
+        storage.save_backfill("test", None, &quarter_hours(full, &[], 1.0), SaveMode::Fill).await.unwrap();
+        // Noon is missing, the first and last quarter hour are there
+        storage.save_backfill("test", None, &quarter_hours(gap, &[48], 1.0), SaveMode::Fill).await.unwrap();
+
+        assert_eq!(storage.partition_state("test", None, full).await.unwrap(), PartitionState::Complete);
+        assert_eq!(storage.partition_state("test", None, gap).await.unwrap(), PartitionState::Incomplete);
+        let missing = NaiveDate::from_ymd_opt(2025, 3, 12).unwrap();
+        assert_eq!(storage.partition_state("test", None, missing).await.unwrap(), PartitionState::Missing);
+    }
+
+    /// Stored `price` and `scraped_at` of every quarter hour of `date`, by start.
+    fn stored_prices(storage: &Storage, date: NaiveDate) -> Vec<(i64, f64, i64)> {
+        let (rows, _) = read_values(Path::new(&storage.partition_path("test", None, date))).unwrap();
+        let mut prices: Vec<_> = rows.into_iter()
+            .map(|((start, _), (scraped_at, values))| (start, values["price"], scraped_at))
+            .collect();
+        prices.sort_by_key(|(start, _, _)| *start);
+        prices
+    }
+
+    #[tokio::test]
+    async fn fill_refreshes_backfilled_rows_and_keeps_live_ones() {
+        let dir = TempDataDir::new("fill");
+        let storage = dir.storage();
+        let date = NaiveDate::from_ymd_opt(2025, 3, 10).unwrap();
+        let second_half: Vec<usize> = (48..96).collect();
+        let first_half: Vec<usize> = (0..48).collect();
+
+        storage.save_if_new("test", None, &quarter_hours(date, &second_half, 1.0)).await.unwrap();
+        storage.save_backfill("test", None, &quarter_hours(date, &first_half, 1.0), SaveMode::Fill).await.unwrap();
+        assert!(storage.save_backfill("test", None, &quarter_hours(date, &[], 2.0), SaveMode::Fill).await.unwrap());
+
+        let prices = stored_prices(&storage, date);
+        assert_eq!(prices.len(), 96);
+        for (price, scraped_at) in prices[..48].iter().map(|(_, p, s)| (*p, *s)) {
+            assert_eq!(price, 1.0);
+            assert_ne!(scraped_at, 0);
+        }
+        for (price, scraped_at) in prices[48..].iter().map(|(_, p, s)| (*p, *s)) {
+            assert_eq!(price, 2.0);
+            assert_eq!(scraped_at, 0);
+        }
+
+        // Nothing left to fill
+        assert!(!storage.save_backfill("test", None, &quarter_hours(date, &[], 2.0), SaveMode::Fill).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn overwrite_replaces_live_values() {
+        let dir = TempDataDir::new("overwrite");
+        let storage = dir.storage();
+        let date = NaiveDate::from_ymd_opt(2025, 3, 10).unwrap();
+
+        storage.save_if_new("test", None, &quarter_hours(date, &[], 1.0)).await.unwrap();
+        assert!(storage.save_backfill("test", None, &quarter_hours(date, &[], 2.0), SaveMode::Overwrite).await.unwrap());
+
+        // Overwritten rows count as backfilled
+        assert!(stored_prices(&storage, date).iter().all(|(_, price, scraped_at)| *price == 2.0 && *scraped_at == 0));
+        assert!(!storage.save_backfill("test", None, &quarter_hours(date, &[], 2.0), SaveMode::Overwrite).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn compare_reports_differences_without_writing() {
+        let dir = TempDataDir::new("compare");
+        let storage = dir.storage();
+        let date = NaiveDate::from_ymd_opt(2025, 3, 10).unwrap();
+
+        storage.save_if_new("test", None, &quarter_hours(date, &[10, 11], 1.0)).await.unwrap();
+        let before = stored_prices(&storage, date);
+        let diff = storage.compare("test", None, &quarter_hours(date, &[], 3.5)).await.unwrap();
+
+        assert_eq!(diff, PartitionDiff { rows_added: 2, values_changed: 94, max_abs_delta: 2.5 });
+        assert_eq!(stored_prices(&storage, date), before);
+    }
+
+    #[test]
+    fn drop_bids_removes_every_row_of_a_bid() {
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("start", DataType::Timestamp(TimeUnit::Microsecond, Some("UTC".into())), false),
+            Field::new("end", DataType::Timestamp(TimeUnit::Microsecond, Some("UTC".into())), false),
+            Field::new("bid_type", DataType::Utf8, false),
+            Field::new("direction", DataType::Utf8, false),
+            Field::new("rank", DataType::Int32, false),
+            Field::new("price", DataType::Float64, true),
+            Field::new("volume", DataType::Float64, true),
+            Field::new("scraped_at", DataType::Timestamp(TimeUnit::Microsecond, Some("UTC".into())), true),
+        ]));
+        // Two rows (history) of rank 1 and one of rank 2
+        let batch = RecordBatch::try_new(schema, vec![
+            Arc::new(TimestampMicrosecondArray::from(vec![0, 0, 0]).with_timezone("UTC")),
+            Arc::new(TimestampMicrosecondArray::from(vec![900, 900, 900]).with_timezone("UTC")),
+            Arc::new(StringArray::from(vec!["aFRR", "aFRR", "aFRR"])),
+            Arc::new(StringArray::from(vec!["Up", "Up", "Up"])),
+            Arc::new(Int32Array::from(vec![1, 1, 2])),
+            Arc::new(Float64Array::from(vec![Some(10.0), Some(12.0), Some(20.0)])),
+            Arc::new(Float64Array::from(vec![Some(5.0), Some(5.0), None])),
+            Arc::new(TimestampMicrosecondArray::from(vec![100, 200, 100]).with_timezone("UTC")),
+        ]).unwrap();
+
+        let keys = HashSet::from([(0, 900, "aFRR".to_string(), "Up".to_string(), 1)]);
+        let kept = drop_bids(&batch, &keys).unwrap();
+
+        assert_eq!(kept.num_rows(), 1);
+        assert_eq!(kept.column(4).as_any().downcast_ref::<Int32Array>().unwrap().value(0), 2);
+    }
+}
@@ -0,0 +1,330 @@
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use tokio::sync::Mutex;
+use tracing::{info, warn};
+
+use crate::metrics::metrics;
+
+/// Set of local files waiting to be uploaded, backed by an append-only journal.
+///
+/// Every insert is appended to the journal before it is acknowledged, so a crash
+/// between `Storage` writing a partition and the next upload cycle does not lose
+/// the file. The journal is rewritten from the in-memory set by `compact` once an
+/// upload cycle has finished.
+pub struct UploadQueue {
+    journal_path: PathBuf,
+    pending: Mutex<HashSet<String>>,
+}
+
+impl UploadQueue {
+    /// Open the queue, replaying any entries left in the journal by a previous run.
+    pub fn open(journal_path: impl Into<PathBuf>) -> Result<Self> {
+        let journal_path = journal_path.into();
+        let pending = Self::replay(&journal_path)?;
+
+        if !pending.is_empty() {
+            info!("Replayed {} pending uploads from {:?}", pending.len(), journal_path);
+        }
+
+        // Drop duplicates and entries for files that no longer exist
+        Self::write_journal(&journal_path, &pending)?;
+        metrics().upload_queue_depth.set(pending.len() as i64);
+
+        Ok(Self {
+            journal_path,
+            pending: Mutex::new(pending),
+        })
+    }
+
+    fn replay(journal_path: &Path) -> Result<HashSet<String>> {
+        let mut pending = HashSet::new();
+        if !journal_path.exists() {
+            return Ok(pending);
+        }
+
+        let file = File::open(journal_path)
+            .with_context(|| format!("Failed to open upload journal {:?}", journal_path))?;
+        for line in BufReader::new(file).lines() {
+            let line = line?;
+            let file_path = line.trim();
+            if file_path.is_empty() {
+                continue;
+            }
+            if Path::new(file_path).exists() {
+                pending.insert(file_path.to_string());
+            } else {
+                warn!("Dropping journal entry for missing file {}", file_path);
+            }
+        }
+
+        Ok(pending)
+    }
+
+    /// Mark a file as pending, recording it in the journal first.
+    pub async fn insert(&self, file_path: String) -> Result<()> {
+        let mut pending = self.pending.lock().await;
+        if pending.contains(&file_path) {
+            return Ok(());
+        }
+
+        self.append_journal(&file_path)
+            .await
+            .with_context(|| format!("Failed to record {} in upload journal", file_path))?;
+        pending.insert(file_path);
+        metrics().upload_queue_depth.set(pending.len() as i64);
+        Ok(())
+    }
+
+    /// Take the pending files accepted by `is_ready` out of the in-memory set; the others
+    /// stay queued without touching the journal.
+    ///
+    /// The journal keeps the drained entries until `compact` is called, so files
+    /// that are mid-upload when the process dies are replayed on the next start.
+    pub async fn drain_ready(&self, is_ready: impl Fn(&str) -> bool) -> Vec<String> {
+        let mut pending = self.pending.lock().await;
+        let files: Vec<String> = pending.iter().filter(|file_path| is_ready(file_path)).cloned().collect();
+        for file_path in &files {
+            pending.remove(file_path);
+        }
+        metrics().upload_queue_depth.set(pending.len() as i64);
+        files
+    }
+
+    /// Put files back into the queue, e.g. after a failed upload.
+    pub async fn requeue(&self, files: Vec<String>) -> Result<()> {
+        for file_path in files {
+            self.insert(file_path).await?;
+        }
+        Ok(())
+    }
+
+    /// Rewrite the journal so it only contains what is currently pending.
+    pub async fn compact(&self) -> Result<()> {
+        let pending = self.pending.lock().await;
+        let journal_path = self.journal_path.clone();
+        let snapshot = pending.clone();
+        tokio::task::spawn_blocking(move || Self::write_journal(&journal_path, &snapshot)).await?
+    }
+
+    pub async fn len(&self) -> usize {
+        self.pending.lock().await.len()
+    }
+
+    pub async fn is_empty(&self) -> bool {
+        self.pending.lock().await.is_empty()
+    }
+
+    /// Append `file_path` to the journal on the blocking pool; the caller holds the lock,
+    /// so entries are written in the order they are inserted.
+    async fn append_journal(&self, file_path: &str) -> Result<()> {
+        let journal_path = self.journal_path.clone();
+        let line = file_path.to_string();
+        tokio::task::spawn_blocking(move || append_line(&journal_path, &line)).await?
+    }
+
+    fn write_journal(journal_path: &Path, pending: &HashSet<String>) -> Result<()> {
+        if let Some(parent) = journal_path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+
+        let tmp_path = journal_path.with_extension("tmp");
+        let mut file = File::create(&tmp_path)?;
+        for file_path in pending {
+            writeln!(file, "{}", file_path)?;
+        }
+        file.sync_all()?;
+
+        // Atomic rename
+        std::fs::rename(&tmp_path, journal_path)?;
+        Ok(())
+    }
+}
+
+/// A file that was given up on, with the error of its last attempt.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DeadLetter {
+    pub file: String,
+    pub error: String,
+    pub attempts: u32,
+    pub failed_at: DateTime<Utc>,
+}
+
+/// Append-only JSON-lines list of files that will not be retried automatically.
+///
+/// Entries stay until the file is rewritten (and queued again) or the list is
+/// deleted by hand; startup reconciliation queues those files again as well.
+pub struct DeadLetterLog {
+    path: PathBuf,
+    count: Mutex<usize>,
+}
+
+impl DeadLetterLog {
+    pub fn open(path: impl Into<PathBuf>) -> Result<Self> {
+        let path = path.into();
+        let count = Self::read(&path)?.len();
+
+        if count > 0 {
+            warn!("{} uploads are in the dead-letter list {:?}", count, path);
+        }
+        metrics().upload_dead_letters.set(count as i64);
+
+        Ok(Self {
+            path,
+            count: Mutex::new(count),
+        })
+    }
+
+    /// Every entry recorded so far, oldest first.
+    pub fn entries(&self) -> Result<Vec<DeadLetter>> {
+        Self::read(&self.path)
+    }
+
+    pub async fn append(&self, entry: &DeadLetter) -> Result<()> {
+        let mut count = self.count.lock().await;
+
+        let path = self.path.clone();
+        let line = serde_json::to_string(entry)?;
+        tokio::task::spawn_blocking(move || append_line(&path, &line)).await?
+            .with_context(|| format!("Failed to write dead-letter list {:?}", self.path))?;
+
+        *count += 1;
+        metrics().upload_dead_letters.set(*count as i64);
+        Ok(())
+    }
+
+    fn read(path: &Path) -> Result<Vec<DeadLetter>> {
+        if !path.exists() {
+            return Ok(Vec::new());
+        }
+
+        let file = File::open(path)
+            .with_context(|| format!("Failed to open dead-letter list {:?}", path))?;
+        let mut entries = Vec::new();
+        for line in BufReader::new(file).lines() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            match serde_json::from_str(&line) {
+                Ok(entry) => entries.push(entry),
+                Err(e) => warn!("Skipping unreadable dead-letter entry {:?}: {}", line, e),
+            }
+        }
+        Ok(entries)
+    }
+}
+
+/// Append one line to `path` and sync it to disk, creating the file and its directory.
+fn append_line(path: &Path, line: &str) -> Result<()> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+
+    let mut file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)?;
+    writeln!(file, "{}", line)?;
+    file.sync_data()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Directory below the system temp dir with a journal and partition files, removed on drop.
+    struct TempDir(PathBuf);
+
+    impl TempDir {
+        fn new(name: &str) -> Self {
+            let path = std::env::temp_dir().join(format!("upload_queue_{}_{}", name, std::process::id()));
+            let _ = std::fs::remove_dir_all(&path);
+            std::fs::create_dir_all(&path).unwrap();
+            Self(path)
+        }
+
+        fn journal(&self) -> PathBuf {
+            self.0.join(".pending_uploads")
+        }
+
+        /// Create a partition file and return its path.
+        fn file(&self, name: &str) -> String {
+            let path = self.0.join(name);
+            std::fs::write(&path, b"data").unwrap();
+            path.to_string_lossy().to_string()
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.0);
+        }
+    }
+
+    #[tokio::test]
+    async fn inserted_files_are_replayed_on_reopen() {
+        let dir = TempDir::new("replay");
+        let queue = UploadQueue::open(dir.journal()).unwrap();
+        queue.insert(dir.file("a.parquet")).await.unwrap();
+        queue.insert(dir.file("b.parquet")).await.unwrap();
+        drop(queue);
+
+        let reopened = UploadQueue::open(dir.journal()).unwrap();
+        assert_eq!(reopened.len().await, 2);
+    }
+
+    #[tokio::test]
+    async fn compact_removes_drained_entries() {
+        let dir = TempDir::new("compact");
+        let queue = UploadQueue::open(dir.journal()).unwrap();
+        let uploaded = dir.file("a.parquet");
+        let waiting = dir.file("b.parquet");
+        queue.insert(uploaded.clone()).await.unwrap();
+        queue.insert(waiting.clone()).await.unwrap();
+
+        assert_eq!(queue.drain_ready(|file_path| file_path == uploaded).await, vec![uploaded]);
+        queue.compact().await.unwrap();
+        drop(queue);
+
+        let reopened = UploadQueue::open(dir.journal()).unwrap();
+        assert_eq!(reopened.drain_ready(|_| true).await, vec![waiting]);
+    }
+
+    #[tokio::test]
+    async fn drained_entries_survive_until_compacted() {
+        let dir = TempDir::new("drained");
+        let queue = UploadQueue::open(dir.journal()).unwrap();
+        queue.insert(dir.file("a.parquet")).await.unwrap();
+        assert_eq!(queue.drain_ready(|_| true).await.len(), 1);
+        assert!(queue.is_empty().await);
+        drop(queue);
+
+        // E.g. the process died mid-upload
+        let reopened = UploadQueue::open(dir.journal()).unwrap();
+        assert_eq!(reopened.len().await, 1);
+    }
+
+    #[tokio::test]
+    async fn entries_of_missing_files_are_dropped() {
+        let dir = TempDir::new("missing");
+        let queue = UploadQueue::open(dir.journal()).unwrap();
+        let deleted = dir.file("a.parquet");
+        let kept = dir.file("b.parquet");
+        queue.insert(deleted.clone()).await.unwrap();
+        queue.insert(kept.clone()).await.unwrap();
+        drop(queue);
+        std::fs::remove_file(&deleted).unwrap();
+
+        let reopened = UploadQueue::open(dir.journal()).unwrap();
+        assert_eq!(reopened.drain_ready(|_| true).await, vec![kept.clone()]);
+        // The journal was rewritten without the entry
+        let journal = std::fs::read_to_string(dir.journal()).unwrap();
+        assert_eq!(journal.lines().collect::<Vec<_>>(), vec![kept.as_str()]);
+    }
+}
@@ -1,25 +1,178 @@
-use anyhow::Result;
+use anyhow::{Context, Result};
 use aws_sdk_s3::Client;
 use aws_sdk_s3::config::Credentials;
+use aws_sdk_s3::error::{ProvideErrorMetadata, SdkError};
+use aws_sdk_s3::primitives::ByteStream;
+use aws_sdk_s3::types::{ChecksumAlgorithm, CompletedMultipartUpload, CompletedPart};
 use aws_config::Region;
-use std::collections::HashSet;
+use base64::Engine;
+use bytes::Bytes;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use futures::stream::{self, StreamExt};
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeSet, HashMap, HashSet};
 use std::env;
-use std::path::Path;
+use std::fmt;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
-use std::time::Duration;
+use std::time::{Duration, UNIX_EPOCH};
+use tokio::io::AsyncReadExt;
 use tokio::sync::Mutex;
-use tokio::time::sleep;
-use tracing::{info, warn};
+use tokio::time::{sleep, Instant};
+use tokio_util::sync::CancellationToken;
+use tracing::{info, warn, error};
+
+use crate::metrics::metrics;
+use crate::upload_queue::{DeadLetter, DeadLetterLog, UploadQueue};
+
+/// Journal of files waiting for upload, kept in the data directory next to the data it refers to
+pub const PENDING_UPLOADS_JOURNAL: &str = ".pending_uploads";
+
+/// Files in the data directory that failed permanently or ran out of attempts, as JSON lines
+pub const DEAD_LETTER_LIST: &str = ".upload_dead_letter";
+
+/// User metadata key holding the hex SHA-256 of the uploaded file
+pub const SHA256_METADATA_KEY: &str = "sha256";
+
+/// S3 error codes that retrying will not fix
+const PERMANENT_ERROR_CODES: &[&str] = &[
+    "AccessDenied",
+    "AccountProblem",
+    "AllAccessDisabled",
+    "InvalidAccessKeyId",
+    "InvalidBucketName",
+    "NoSuchBucket",
+    "SignatureDoesNotMatch",
+];
+
+/// Attempts per multipart part before the whole file is given up for this cycle
+const PART_ATTEMPTS: u32 = 3;
+
+/// S3 accepts at most this many parts per multipart upload
+const MAX_PARTS: u64 = 10_000;
+
+/// How many files are uploaded at once, when to switch to multipart uploads
+/// and how failed files are retried.
+#[derive(Debug, Clone)]
+pub struct UploadSettings {
+    pub concurrency: usize,
+    pub multipart_threshold: u64,
+    pub part_size: u64,
+    /// Failed attempts after which a file is moved to the dead-letter list
+    pub max_attempts: u32,
+    /// Delay before the first retry, doubled after every further failure
+    pub retry_base: Duration,
+    pub retry_max: Duration,
+}
+
+impl UploadSettings {
+    /// Delay before the next attempt after `attempts` failures.
+    fn backoff(&self, attempts: u32) -> Duration {
+        let factor = 2u32.saturating_pow(attempts.saturating_sub(1));
+        self.retry_base.saturating_mul(factor).min(self.retry_max)
+    }
+}
+
+impl Default for UploadSettings {
+    fn default() -> Self {
+        Self {
+            concurrency: 4,
+            multipart_threshold: 64 * 1024 * 1024,
+            part_size: 16 * 1024 * 1024,
+            max_attempts: 10,
+            retry_base: Duration::from_secs(60),
+            retry_max: Duration::from_secs(60 * 60),
+        }
+    }
+}
+
+/// Outcome of uploading the queued files once.
+#[derive(Debug, Default)]
+pub struct UploadReport {
+    pub uploaded: Vec<String>,
+    /// Files that failed with the error; they stay queued and are retried after a backoff
+    pub failed: Vec<(String, String)>,
+    /// Files that failed permanently or ran out of attempts; they are not retried
+    pub dead_lettered: Vec<(String, String)>,
+}
+
+impl UploadReport {
+    pub fn is_success(&self) -> bool {
+        self.failed.is_empty() && self.dead_lettered.is_empty()
+    }
+}
+
+/// Failure history of a queued file.
+struct RetryState {
+    attempts: u32,
+    next_attempt_at: Instant,
+}
+
+/// Upload error that retrying will not fix, e.g. AccessDenied or a missing local file.
+#[derive(Debug)]
+struct PermanentError {
+    reason: String,
+    source: Box<dyn std::error::Error + Send + Sync>,
+}
+
+impl fmt::Display for PermanentError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} (permanent)", self.reason)
+    }
+}
+
+impl std::error::Error for PermanentError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(self.source.as_ref())
+    }
+}
+
+fn is_permanent(error: &anyhow::Error) -> bool {
+    error.chain().any(|cause| cause.is::<PermanentError>())
+}
+
+/// Wrap S3 errors with a permanent error code (or requests that could not be built)
+/// in `PermanentError`; timeouts, throttling and server errors stay transient.
+fn classify<E, R>(error: SdkError<E, R>) -> anyhow::Error
+where
+    E: ProvideErrorMetadata + std::error::Error + Send + Sync + 'static,
+    R: fmt::Debug + Send + Sync + 'static,
+{
+    let permanent_reason = match &error {
+        SdkError::ServiceError(service) => service.err().code()
+            .filter(|code| PERMANENT_ERROR_CODES.contains(code))
+            .map(str::to_string),
+        SdkError::ConstructionFailure(_) => Some("request could not be built".to_string()),
+        _ => None,
+    };
+
+    match permanent_reason {
+        Some(reason) => PermanentError { reason, source: Box::new(error) }.into(),
+        None => error.into(),
+    }
+}
 
 pub struct Uploader {
     client: Client,
     bucket: String,
     prefix: String,
-    pending_files: Arc<Mutex<HashSet<String>>>,
+    /// Local directory whose layout is mirrored below the S3 prefix
+    data_dir: PathBuf,
+    settings: UploadSettings,
+    pending_files: Arc<UploadQueue>,
+    retries: Mutex<HashMap<String, RetryState>>,
+    dead_letters: DeadLetterLog,
 }
 
 impl Uploader {
-    pub async fn new(bucket: String, region: Option<String>, endpoint: Option<String>, prefix: String) -> Result<Self> {
+    pub async fn new(
+        bucket: String,
+        region: Option<String>,
+        endpoint: Option<String>,
+        prefix: String,
+        data_dir: &str,
+        settings: UploadSettings,
+    ) -> Result<Self> {
         let region = region.unwrap_or_else(|| "eu-central".to_string());
         
         let mut s3_config_builder = aws_sdk_s3::config::Builder::new()
@@ -52,70 +205,520 @@ impl Uploader {
         
         let client = Client::from_conf(s3_config_builder.build());
         
+        // Replay files that were marked dirty but not uploaded before the last shutdown
+        let data_dir = PathBuf::from(data_dir);
+        let pending_files = UploadQueue::open(data_dir.join(PENDING_UPLOADS_JOURNAL))?;
+        let dead_letters = DeadLetterLog::open(data_dir.join(DEAD_LETTER_LIST))?;
+        
         Ok(Self {
             client,
             bucket,
             prefix,
-            pending_files: Arc::new(Mutex::new(HashSet::new())),
+            data_dir,
+            settings,
+            pending_files: Arc::new(pending_files),
+            retries: Mutex::new(HashMap::new()),
+            dead_letters,
         })
     }
 
-    pub fn get_pending_files_handle(&self) -> Arc<Mutex<HashSet<String>>> {
+    pub fn get_pending_files_handle(&self) -> Arc<UploadQueue> {
         self.pending_files.clone()
     }
 
-    pub async fn run(&self) {
+    pub fn dead_letter_path(&self) -> PathBuf {
+        self.data_dir.join(DEAD_LETTER_LIST)
+    }
+
+    /// Upload queued files every 60 seconds until `shutdown` is cancelled.
+    /// Files that failed before are only retried once their backoff has passed.
+    pub async fn run(&self, shutdown: CancellationToken) {
         info!("Starting S3 uploader for bucket: {}", self.bucket);
         
         loop {
-            sleep(Duration::from_secs(60)).await;
+            tokio::select! {
+                _ = shutdown.cancelled() => break,
+                _ = sleep(Duration::from_secs(60)) => {}
+            }
             
-            let files_to_upload = {
-                let mut pending = self.pending_files.lock().await;
-                let files: Vec<String> = pending.drain().collect();
-                files
+            self.upload_queued(false).await;
+        }
+    }
+
+    /// Final upload cycles on shutdown: one attempt for every queued file, then retries
+    /// of files whose backoff runs out, until the queue is empty or `deadline` passes.
+    /// A cycle still running at the deadline is cancelled; its files stay in the journal.
+    /// Returns the number of files still pending.
+    pub async fn finish(&self, deadline: Instant) -> usize {
+        let mut ignore_backoff = true;
+        loop {
+            let queued = self.pending_files.len().await;
+            if queued == 0 || Instant::now() >= deadline {
+                return queued;
+            }
+
+            let Ok(report) = tokio::time::timeout_at(deadline, self.upload_queued(ignore_backoff)).await else {
+                return queued.max(self.pending_files.len().await);
             };
+            ignore_backoff = false;
 
-            if files_to_upload.is_empty() {
-                continue;
+            if report.uploaded.is_empty() || !report.is_success() {
+                // Don't hammer S3 while it is failing or every queued file is backing off
+                tokio::select! {
+                    _ = sleep(Duration::from_secs(1)) => {}
+                    _ = tokio::time::sleep_until(deadline) => {}
+                }
             }
+        }
+    }
+
+    /// Upload everything currently queued right away, ignoring backoff.
+    /// Failures are requeued or moved to the dead-letter list.
+    pub async fn flush(&self) -> UploadReport {
+        self.upload_queued(true).await
+    }
+
+    async fn upload_queued(&self, ignore_backoff: bool) -> UploadReport {
+        // Files still backing off stay in the queue
+        let now = Instant::now();
+        let files_to_upload = {
+            let retries = self.retries.lock().await;
+            self.pending_files.drain_ready(|file_path| {
+                ignore_backoff || retries.get(file_path).is_none_or(|retry| retry.next_attempt_at <= now)
+            }).await
+        };
+        let mut report = UploadReport::default();
+
+        if files_to_upload.is_empty() {
+            return report;
+        }
+
+        info!("Uploading {} files to S3 ({} at a time)", files_to_upload.len(), self.settings.concurrency);
+
+        let results: Vec<(String, Result<()>)> = stream::iter(files_to_upload)
+            .map(|file_path| async move {
+                let result = self.upload_file(&file_path).await;
+                (file_path, result)
+            })
+            .buffer_unordered(self.settings.concurrency.max(1))
+            .collect()
+            .await;
+
+        let mut requeue = Vec::new();
+        for (file_path, result) in results {
+            match result {
+                Ok(()) => {
+                    self.retries.lock().await.remove(&file_path);
+                    report.uploaded.push(file_path);
+                }
+                Err(e) => {
+                    metrics().upload_failures.inc();
+                    if self.record_failure(&file_path, &e).await {
+                        report.failed.push((file_path.clone(), format!("{:#}", e)));
+                        requeue.push(file_path);
+                    } else {
+                        report.dead_lettered.push((file_path, format!("{:#}", e)));
+                    }
+                }
+            }
+        }
+
+        self.requeue(requeue).await;
+        report
+    }
+
+    async fn requeue(&self, files: Vec<String>) {
+        if let Err(e) = self.pending_files.requeue(files).await {
+            error!("Failed to requeue failed uploads: {:?}", e);
+        }
+
+        if let Err(e) = self.pending_files.compact().await {
+            error!("Failed to compact upload journal: {:?}", e);
+        }
+    }
+
+    /// Count a failed attempt and schedule the next one. Returns false when the file
+    /// was moved to the dead-letter list instead.
+    async fn record_failure(&self, file_path: &str, error: &anyhow::Error) -> bool {
+        let permanent = is_permanent(error);
+        let attempts = {
+            let mut retries = self.retries.lock().await;
+            let retry = retries.entry(file_path.to_string()).or_insert(RetryState {
+                attempts: 0,
+                next_attempt_at: Instant::now(),
+            });
+            retry.attempts += 1;
+
+            if !permanent && retry.attempts < self.settings.max_attempts {
+                let delay = self.settings.backoff(retry.attempts);
+                retry.next_attempt_at = Instant::now() + delay;
+                warn!("Failed to upload {} (attempt {}/{}): {:?}. Retrying in {}s.",
+                    file_path, retry.attempts, self.settings.max_attempts, error, delay.as_secs());
+                return true;
+            }
+
+            let attempts = retry.attempts;
+            retries.remove(file_path);
+            attempts
+        };
+
+        error!("Giving up on {} after {} attempt(s), moving it to the dead-letter list: {:?}",
+            file_path, attempts, error);
+        let entry = DeadLetter {
+            file: file_path.to_string(),
+            error: format!("{:#}", error),
+            attempts,
+            failed_at: chrono::Utc::now(),
+        };
+        if let Err(e) = self.dead_letters.append(&entry).await {
+            error!("Failed to record {} in the dead-letter list: {:?}", file_path, e);
+        }
+        false
+    }
+
+    /// Compare the local data tree with the bucket and queue every partition that is
+    /// missing in S3 or differs from the uploaded object. Returns the number of files queued.
+    pub async fn reconcile(&self) -> Result<usize> {
+        // Walk the data tree on the blocking pool
+        let data_dir = self.data_dir.clone();
+        let local_files = tokio::task::spawn_blocking(move || -> Result<Vec<String>> {
+            let mut local_files = Vec::new();
+            if data_dir.exists() {
+                collect_partition_files(&data_dir, &mut local_files)?;
+            }
+            Ok(local_files)
+        }).await??;
+
+        if local_files.is_empty() {
+            return Ok(0);
+        }
+
+        // List each dataset folder once instead of issuing a HEAD request per partition
+        let datasets: BTreeSet<String> = local_files.iter()
+            .filter_map(|file_path| dataset_folder(&self.data_dir, file_path))
+            .collect();
 
-            info!("Uploading {} files to S3", files_to_upload.len());
+        let mut remote_objects = HashMap::new();
+        for dataset in &datasets {
+            let dataset_prefix = format!("{}{}/", self.prefix, dataset);
+            remote_objects.extend(self.list_objects(&dataset_prefix).await?);
+        }
 
-            let mut failed_uploads = Vec::new();
+        info!("Reconciling {} local partitions against {} objects in S3", local_files.len(), remote_objects.len());
 
-            for file_path in files_to_upload {
-                if let Err(e) = self.upload_file(&file_path).await {
-                    warn!("Failed to upload {}: {:?}. Will retry in next cycle.", file_path, e);
-                    failed_uploads.push(file_path);
+        let mut queued = 0;
+        let mut uploaded = Vec::new();
+        for file_path in local_files {
+            let key = self.s3_key(&file_path)?;
+            match remote_objects.remove(&key) {
+                None => {
+                    info!("Not in S3, queueing {}", file_path);
+                    self.pending_files.insert(file_path).await?;
+                    queued += 1;
                 }
+                Some(remote) => uploaded.push((file_path, key, remote)),
             }
+        }
 
-            if !failed_uploads.is_empty() {
-                let mut pending = self.pending_files.lock().await;
-                for file_path in failed_uploads {
-                    pending.insert(file_path);
+        // One HEAD request per object of the same size, `upload_concurrency` at a time
+        let stale_files: Vec<String> = stream::iter(uploaded)
+            .map(|(file_path, key, remote)| async move {
+                match self.is_stale(&file_path, &key, &remote).await {
+                    Ok(true) => Some(file_path),
+                    Ok(false) => None,
+                    Err(e) => {
+                        warn!("Failed to compare {} with S3, skipping it: {:?}", file_path, e);
+                        None
+                    }
                 }
+            })
+            .buffer_unordered(self.settings.concurrency.max(1))
+            .filter_map(|stale| async move { stale })
+            .collect()
+            .await;
+
+        for file_path in stale_files {
+            info!("S3 object differs from local file, queueing {}", file_path);
+            self.pending_files.insert(file_path).await?;
+            queued += 1;
+        }
+
+        Ok(queued)
+    }
+
+    /// The partition files of `files` that have no object in S3. Each dataset folder is
+    /// listed once; the files don't have to exist locally.
+    pub async fn missing_in_s3(&self, files: &[String]) -> Result<HashSet<String>> {
+        let datasets: BTreeSet<String> = files.iter()
+            .filter_map(|file_path| dataset_folder(&self.data_dir, file_path))
+            .collect();
+
+        let mut remote_objects = HashMap::new();
+        for dataset in &datasets {
+            let dataset_prefix = format!("{}{}/", self.prefix, dataset);
+            remote_objects.extend(self.list_objects(&dataset_prefix).await?);
+        }
+
+        let mut missing = HashSet::new();
+        for file_path in files {
+            if !remote_objects.contains_key(&self.s3_key(file_path)?) {
+                missing.insert(file_path.clone());
             }
         }
+        Ok(missing)
     }
 
-    async fn upload_file(&self, file_path: &str) -> Result<()> {
-        let path = Path::new(file_path);
-        let relative_path = path.strip_prefix("data/")?.to_string_lossy();
-        let key = format!("{}{}", self.prefix, relative_path);
-        
-        let body = aws_sdk_s3::primitives::ByteStream::from_path(path).await?;
+    async fn list_objects(&self, prefix: &str) -> Result<HashMap<String, RemoteObject>> {
+        let mut objects = HashMap::new();
+        let mut pages = self.client
+            .list_objects_v2()
+            .bucket(&self.bucket)
+            .prefix(prefix)
+            .into_paginator()
+            .send();
+
+        while let Some(page) = pages.next().await {
+            for object in page?.contents() {
+                if let Some(key) = object.key() {
+                    objects.insert(key.to_string(), RemoteObject {
+                        size: object.size().unwrap_or(0),
+                        last_modified: object.last_modified().map(|t| t.secs()),
+                    });
+                }
+            }
+        }
 
-        self.client
-            .put_object()
+        Ok(objects)
+    }
+
+    /// Decide whether the uploaded object is out of date with the local file.
+    ///
+    /// Sizes are compared first. Objects of the same size are compared by the SHA-256 stored
+    /// in their metadata, which multipart uploads carry as well. Objects uploaded before
+    /// checksums were added count as stale when the local file was modified after the upload.
+    async fn is_stale(&self, file_path: &str, key: &str, remote: &RemoteObject) -> Result<bool> {
+        let metadata = tokio::fs::metadata(file_path).await?;
+        if metadata.len() as i64 != remote.size {
+            return Ok(true);
+        }
+
+        let head = self.client
+            .head_object()
             .bucket(&self.bucket)
-            .key(&key)
-            .body(body)
+            .key(key)
             .send()
             .await?;
 
+        match head.metadata().and_then(|m| m.get(SHA256_METADATA_KEY)) {
+            Some(remote_sha256) => {
+                let path = PathBuf::from(file_path);
+                let local_sha256 = tokio::task::spawn_blocking(move || file_sha256(&path)).await??;
+                Ok(!hex(&local_sha256).eq_ignore_ascii_case(remote_sha256))
+            }
+            None => {
+                let local_modified = metadata.modified()?.duration_since(UNIX_EPOCH)?.as_secs() as i64;
+                Ok(remote.last_modified.is_some_and(|uploaded| local_modified > uploaded))
+            }
+        }
+    }
+
+    fn s3_key(&self, file_path: &str) -> Result<String> {
+        let path = Path::new(file_path);
+        let relative_path = path.strip_prefix(&self.data_dir)?.to_string_lossy();
+        Ok(format!("{}{}", self.prefix, relative_path))
+    }
+
+    async fn upload_file(&self, file_path: &str) -> Result<()> {
+        let path = Path::new(file_path);
+        let key = self.s3_key(file_path)?;
+        
+        let size = match tokio::fs::metadata(path).await {
+            Ok(metadata) => metadata.len(),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                return Err(PermanentError { reason: "local file is missing".to_string(), source: Box::new(e) }.into());
+            }
+            Err(e) => return Err(e.into()),
+        };
+
+        let hash_path = path.to_path_buf();
+        let sha256 = tokio::task::spawn_blocking(move || file_sha256(&hash_path)).await??;
+
+        if size > self.settings.multipart_threshold {
+            self.upload_multipart(path, &key, size, &sha256).await?;
+        } else {
+            let body = ByteStream::from_path(path).await?;
+
+            // S3 rejects the upload if the received bytes don't match the checksum
+            self.client
+                .put_object()
+                .bucket(&self.bucket)
+                .key(&key)
+                .body(body)
+                .checksum_sha256(BASE64.encode(sha256))
+                .metadata(SHA256_METADATA_KEY, hex(&sha256))
+                .send()
+                .await
+                .map_err(classify)?;
+        }
+
+        metrics().uploads.inc();
+        metrics().bytes_uploaded.inc_by(size);
+        metrics().last_upload_success.set(chrono::Utc::now().timestamp() as f64);
         info!("Uploaded {}", key);
         Ok(())
     }
+
+    /// Upload a large file in parts, retrying each part, and abort the upload on failure
+    /// so no orphaned parts are left in the bucket.
+    ///
+    /// S3 only keeps a checksum of the part checksums for multipart objects, so each part
+    /// is checked on upload and the whole-file SHA-256 is stored in the metadata only.
+    async fn upload_multipart(&self, path: &Path, key: &str, size: u64, sha256: &[u8]) -> Result<()> {
+        let upload = self.client
+            .create_multipart_upload()
+            .bucket(&self.bucket)
+            .key(key)
+            .checksum_algorithm(ChecksumAlgorithm::Sha256)
+            .metadata(SHA256_METADATA_KEY, hex(sha256))
+            .send()
+            .await
+            .map_err(classify)?;
+        let upload_id = upload.upload_id().context("S3 returned no multipart upload id")?;
+
+        match self.upload_parts(path, key, upload_id, size).await {
+            Ok(parts) => {
+                self.client
+                    .complete_multipart_upload()
+                    .bucket(&self.bucket)
+                    .key(key)
+                    .upload_id(upload_id)
+                    .multipart_upload(CompletedMultipartUpload::builder().set_parts(Some(parts)).build())
+                    .send()
+                    .await
+                    .map_err(classify)?;
+                Ok(())
+            }
+            Err(e) => {
+                if let Err(abort_error) = self.client
+                    .abort_multipart_upload()
+                    .bucket(&self.bucket)
+                    .key(key)
+                    .upload_id(upload_id)
+                    .send()
+                    .await
+                {
+                    warn!("Failed to abort multipart upload of {}: {:?}", key, abort_error);
+                }
+                Err(e)
+            }
+        }
+    }
+
+    async fn upload_parts(&self, path: &Path, key: &str, upload_id: &str, size: u64) -> Result<Vec<CompletedPart>> {
+        let part_size = part_size_for(size, self.settings.part_size);
+        if part_size > self.settings.part_size {
+            info!("Uploading {} in {} MiB parts to stay within {} parts", key, part_size / MIB, MAX_PARTS);
+        }
+        let part_count = size.div_ceil(part_size);
+        let mut file = tokio::fs::File::open(path).await?;
+        let mut parts = Vec::with_capacity(part_count as usize);
+
+        for part_number in 1..=part_count as i32 {
+            let offset = (part_number as u64 - 1) * part_size;
+            let mut buffer = vec![0; part_size.min(size - offset) as usize];
+            file.read_exact(&mut buffer).await
+                .with_context(|| format!("Failed to read part {} of {:?}", part_number, path))?;
+            // Cloned for every attempt without copying the part
+            let buffer = Bytes::from(buffer);
+            let part_checksum = BASE64.encode(Sha256::digest(&buffer));
+
+            let mut attempt = 1;
+            let output = loop {
+                let result = self.client
+                    .upload_part()
+                    .bucket(&self.bucket)
+                    .key(key)
+                    .upload_id(upload_id)
+                    .part_number(part_number)
+                    .body(ByteStream::from(buffer.clone()))
+                    .checksum_sha256(&part_checksum)
+                    .send()
+                    .await
+                    .map_err(classify);
+
+                match result {
+                    Ok(output) => break output,
+                    Err(e) if attempt < PART_ATTEMPTS && !is_permanent(&e) => {
+                        warn!("Part {}/{} of {} failed (attempt {}): {:?}", part_number, part_count, key, attempt, e);
+                        sleep(Duration::from_secs(2u64.pow(attempt))).await;
+                        attempt += 1;
+                    }
+                    Err(e) => return Err(e).with_context(|| format!("Part {} of {} failed", part_number, key)),
+                }
+            };
+
+            parts.push(CompletedPart::builder()
+                .set_e_tag(output.e_tag().map(str::to_string))
+                .checksum_sha256(part_checksum)
+                .part_number(part_number)
+                .build());
+        }
+
+        Ok(parts)
+    }
+}
+
+const MIB: u64 = 1024 * 1024;
+
+/// Part size for a multipart upload of `size` bytes: `configured`, raised to whole MiB
+/// where the file would otherwise need more than `MAX_PARTS` parts.
+fn part_size_for(size: u64, configured: u64) -> u64 {
+    let smallest = size.div_ceil(MAX_PARTS).div_ceil(MIB) * MIB;
+    configured.max(smallest)
+}
+
+struct RemoteObject {
+    size: i64,
+    /// Upload time in seconds since the epoch
+    last_modified: Option<i64>,
+}
+
+/// SHA-256 of a local file, read in chunks.
+pub fn file_sha256(path: &Path) -> Result<[u8; 32]> {
+    let mut file = std::fs::File::open(path)
+        .with_context(|| format!("Failed to open {:?} for hashing", path))?;
+    let mut hasher = Sha256::new();
+    std::io::copy(&mut file, &mut hasher)?;
+    Ok(hasher.finalize().into())
+}
+
+pub fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Recursively collect every `data.parquet` partition below `path`.
+fn collect_partition_files(path: &Path, files: &mut Vec<String>) -> Result<()> {
+    for entry in std::fs::read_dir(path)? {
+        let entry = entry?;
+        let entry_path = entry.path();
+        let is_hidden = entry.file_name().to_string_lossy().starts_with('.');
+
+        if is_hidden {
+            continue;
+        }
+        if entry_path.is_dir() {
+            collect_partition_files(&entry_path, files)?;
+        } else if entry.file_name() == "data.parquet" {
+            files.push(entry_path.to_string_lossy().to_string());
+        }
+    }
+    Ok(())
+}
+
+/// Dataset folder of a partition file relative to the data directory,
+/// e.g. `apg/imbalance/PT15M` for `data/apg/imbalance/PT15M/year=2025/.../data.parquet`.
+fn dataset_folder(data_dir: &Path, file_path: &str) -> Option<String> {
+    let relative = Path::new(file_path).strip_prefix(data_dir).ok()?.to_string_lossy().to_string();
+    let (dataset, _) = relative.split_once("/year=")?;
+    Some(dataset.to_string())
 }
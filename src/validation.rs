@@ -0,0 +1,99 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use crate::alerting::{Alerter, FeedThresholds, FeedTracker};
+use crate::config::AppConfig;
+use crate::scraper_factory;
+use crate::schedule::Schedule;
+
+/// Check the whole config and return every problem found, so they can all be fixed in one go.
+///
+/// Every scraper is instantiated through `scraper_factory`, but no requests are made.
+pub fn validate_config(config: &AppConfig) -> Vec<String> {
+    let mut problems = Vec::new();
+    let mut names: HashMap<&str, usize> = HashMap::new();
+    let mut folders: HashMap<&str, &str> = HashMap::new();
+
+    if config.scrapers.is_empty() {
+        problems.push("No scrapers configured".to_string());
+    }
+
+    for scraper in &config.scrapers {
+        let name = scraper.scraper_config.name.as_str();
+
+        if name.trim().is_empty() {
+            problems.push("Scraper with an empty name".to_string());
+        }
+
+        *names.entry(name).or_default() += 1;
+
+        if scraper.scraper_config.workers == 0 {
+            problems.push(format!("{}: workers must be at least 1", name));
+        }
+
+        // Scrapers writing to the same folder would overwrite each other's partitions
+        let folder = scraper.sub_data_folder.as_deref().unwrap_or(name);
+        if let Some(other) = folders.insert(folder, name) {
+            problems.push(format!("{}: data folder '{}' is also used by {}", name, folder, other));
+        }
+
+        match scraper_factory::resolve_kind(scraper) {
+            Ok(kind) => {
+                for key in scraper_factory::missing_keys(kind, &scraper.scraper_config) {
+                    problems.push(format!("{}: missing required key {} for kind '{}'", name, key, kind));
+                }
+                if let Err(e) = scraper_factory::create_scraper(scraper) {
+                    problems.push(format!("{}: failed to create scraper: {}", name, e));
+                }
+            }
+            Err(e) => problems.push(format!("{}: {}", name, e)),
+        }
+
+        if let Err(e) = Schedule::from_config(scraper.schedule.as_ref(), scraper.scraper_config.task_generator_delay_ms) {
+            problems.push(format!("{}: invalid schedule: {:#}", name, e));
+        }
+
+        if let Err(e) = scraper.stale_after(config.stale_after.as_deref()) {
+            problems.push(format!("{}: {:#}", name, e));
+        }
+
+        if let Some(alert) = &scraper.alert {
+            if let Err(e) = FeedThresholds::from_config(alert) {
+                problems.push(format!("{}: {:#}", name, e));
+            }
+            if config.alerting.is_none() {
+                problems.push(format!("{}: alert thresholds are set but no alerting webhook is configured", name));
+            }
+        }
+
+        if let Err(e) = scraper.scrape_window() {
+            problems.push(format!("{}: invalid scrape window: {:#}", name, e));
+        }
+
+        if let Err(e) = scraper.backfill_chunk() {
+            problems.push(format!("{}: invalid backfill_chunk: {:#}", name, e));
+        }
+    }
+
+    if let Err(e) = config.upload_settings() {
+        problems.push(format!("{:#}", e));
+    }
+
+    if let Err(e) = config.shutdown_timeout() {
+        problems.push(format!("{:#}", e));
+    }
+
+    if let Some(alerting) = &config.alerting {
+        if let Err(e) = Alerter::new(alerting, Arc::new(FeedTracker::new())) {
+            problems.push(format!("alerting: {:#}", e));
+        }
+    }
+
+    let mut duplicates: Vec<_> = names.into_iter().filter(|(_, count)| *count > 1).collect();
+    duplicates.sort();
+    for (name, count) in duplicates {
+        problems.push(format!("{}: name is used by {} scrapers", name, count));
+    }
+
+    problems
+}
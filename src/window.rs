@@ -0,0 +1,232 @@
+use anyhow::{anyhow, bail, Result};
+use chrono::{DateTime, Datelike, Duration, NaiveDate, TimeZone, Utc};
+use chrono_tz::Tz;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+use crate::config::{parse_duration, parse_timezone};
+
+/// Date range passed to `Scraper::scrape_data`, relative to the time of the scrape.
+///
+/// Defaults to one day back and one day ahead. With `align_to_day` the range is widened
+/// to whole local days in `timezone` (default Europe/Vienna); `offset` shifts both ends.
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct WindowConfig {
+    pub lookback: Option<String>,
+    pub lookahead: Option<String>,
+    pub align_to_day: Option<bool>,
+    pub timezone: Option<String>,
+    pub offset: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct ScrapeWindow {
+    lookback: Duration,
+    lookahead: Duration,
+    align_to_day: bool,
+    timezone: Tz,
+    offset: Duration,
+}
+
+impl ScrapeWindow {
+    pub fn from_config(config: Option<&WindowConfig>) -> Result<Self> {
+        let parse_or = |value: Option<&String>, default: Duration| -> Result<Duration> {
+            value.map(|v| parse_duration(v)).unwrap_or(Ok(default))
+        };
+
+        let (lookback, lookahead, align_to_day, timezone, offset) = match config {
+            Some(config) => (
+                parse_or(config.lookback.as_ref(), Duration::days(1))?,
+                parse_or(config.lookahead.as_ref(), Duration::days(1))?,
+                config.align_to_day.unwrap_or(false),
+                parse_timezone(config.timezone.as_deref().unwrap_or("Europe/Vienna"))?,
+                parse_or(config.offset.as_ref(), Duration::zero())?,
+            ),
+            None => (Duration::days(1), Duration::days(1), false, chrono_tz::Europe::Vienna, Duration::zero()),
+        };
+
+        if lookback < Duration::zero() || lookahead < Duration::zero() {
+            bail!("window.lookback and window.lookahead must not be negative");
+        }
+
+        Ok(Self { lookback, lookahead, align_to_day, timezone, offset })
+    }
+
+    /// Window with both ends moved by `offset`.
+    pub fn shifted(mut self, offset: Duration) -> Self {
+        self.offset += offset;
+        self
+    }
+
+    /// Range to scrape at `reference` (normally now).
+    pub fn range(&self, reference: DateTime<Utc>) -> (DateTime<Utc>, DateTime<Utc>) {
+        self.range_between(reference, reference)
+    }
+
+    /// Exactly the local days `first..=last` in Europe/Vienna, the days partitions are stored
+    /// by, moved by `offset`. Used by the backfill tool, which needs each period only once.
+    pub fn range_for_days(&self, first: NaiveDate, last: NaiveDate) -> (DateTime<Utc>, DateTime<Utc>) {
+        let start = local_day_start(first, &chrono_tz::Europe::Vienna);
+        let end = local_day_start(last + Duration::days(1), &chrono_tz::Europe::Vienna);
+        (start + self.offset, end + self.offset)
+    }
+
+    fn range_between(&self, from: DateTime<Utc>, to: DateTime<Utc>) -> (DateTime<Utc>, DateTime<Utc>) {
+        let mut start = from - self.lookback;
+        let mut end = to + self.lookahead;
+
+        if self.align_to_day {
+            start = local_day_start(local_date(start, &self.timezone), &self.timezone);
+            let end_date = local_date(end, &self.timezone);
+            if local_day_start(end_date, &self.timezone) != end {
+                end = local_day_start(end_date + Duration::days(1), &self.timezone);
+            }
+        }
+
+        (start + self.offset, end + self.offset)
+    }
+}
+
+/// Local calendar date of `instant` in `timezone`.
+pub fn local_date(instant: DateTime<Utc>, timezone: &Tz) -> NaiveDate {
+    instant.with_timezone(timezone).date_naive()
+}
+
+/// Start of the local day `date` in `timezone`, as UTC.
+pub fn local_day_start(date: NaiveDate, timezone: &Tz) -> DateTime<Utc> {
+    let midnight = date.and_hms_opt(0, 0, 0).unwrap_or_default();
+    timezone.from_local_datetime(&midnight)
+        .earliest()
+        // Midnight skipped by a DST change: fall back to interpreting it as UTC
+        .map(|t| t.with_timezone(&Utc))
+        .unwrap_or_else(|| midnight.and_utc())
+}
+
+/// Days fetched per backfill request: `7d` for a week, `1M` for whole calendar months.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChunkSize {
+    Days(u32),
+    /// Calendar months, so chunks start on the 1st
+    Months(u32),
+}
+
+impl ChunkSize {
+    pub fn parse(value: &str) -> Result<Self> {
+        let value = value.trim();
+        let split = value.find(|c: char| !c.is_ascii_digit()).unwrap_or(value.len());
+        let (amount, unit) = value.split_at(split);
+        let amount: u32 = amount.parse().ok().filter(|amount| *amount > 0)
+            .ok_or_else(|| anyhow!("Invalid chunk size '{}', expected e.g. 1d, 7d or 1M", value))?;
+
+        match unit {
+            "d" => Ok(Self::Days(amount)),
+            "M" => Ok(Self::Months(amount)),
+            _ => bail!("Invalid chunk size unit in '{}', expected d (days) or M (months)", value),
+        }
+    }
+
+    /// Group sorted `dates` into runs of consecutive days that each fit in one chunk.
+    pub fn split(self, dates: &[NaiveDate]) -> Vec<(NaiveDate, NaiveDate)> {
+        let mut runs: Vec<(NaiveDate, NaiveDate)> = Vec::new();
+        for &date in dates {
+            match runs.last_mut() {
+                Some((first, last)) if *last + Duration::days(1) == date && self.same_chunk(*first, date) => {
+                    *last = date;
+                }
+                _ => runs.push((date, date)),
+            }
+        }
+        runs
+    }
+
+    fn same_chunk(self, first: NaiveDate, date: NaiveDate) -> bool {
+        match self {
+            Self::Days(days) => (date - first).num_days() < days as i64,
+            Self::Months(months) => {
+                let chunk = |d: NaiveDate| (d.year() * 12 + d.month0() as i32) / months as i32;
+                chunk(first) == chunk(date)
+            }
+        }
+    }
+}
+
+impl fmt::Display for ChunkSize {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ChunkSize::Days(days) => write!(f, "{}d", days),
+            ChunkSize::Months(months) => write!(f, "{}M", months),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(s: &str) -> NaiveDate {
+        s.parse().unwrap()
+    }
+
+    fn days(first: &str, last: &str) -> Vec<NaiveDate> {
+        date(first).iter_days().take_while(|d| *d <= date(last)).collect()
+    }
+
+    #[test]
+    fn chunk_sizes_parse() {
+        assert_eq!(ChunkSize::parse("7d").unwrap(), ChunkSize::Days(7));
+        assert_eq!(ChunkSize::parse(" 1M ").unwrap(), ChunkSize::Months(1));
+        assert!(ChunkSize::parse("0d").is_err());
+        assert!(ChunkSize::parse("1x").is_err());
+        assert!(ChunkSize::parse("d").is_err());
+    }
+
+    #[test]
+    fn monthly_chunks_follow_calendar_months() {
+        let runs = ChunkSize::Months(1).split(&days("2024-01-01", "2024-12-31"));
+        assert_eq!(runs.len(), 12);
+        assert_eq!(runs[1], (date("2024-02-01"), date("2024-02-29")));
+        assert_eq!(runs[11], (date("2024-12-01"), date("2024-12-31")));
+
+        let runs = ChunkSize::Months(3).split(&days("2024-02-15", "2024-04-10"));
+        assert_eq!(runs, [(date("2024-02-15"), date("2024-03-31")), (date("2024-04-01"), date("2024-04-10"))]);
+    }
+
+    #[test]
+    fn gaps_split_runs() {
+        let mut dates = days("2024-03-01", "2024-03-05");
+        dates.extend(days("2024-03-08", "2024-03-09"));
+
+        let runs = ChunkSize::Months(1).split(&dates);
+        assert_eq!(runs, [(date("2024-03-01"), date("2024-03-05")), (date("2024-03-08"), date("2024-03-09"))]);
+    }
+
+    #[test]
+    fn day_chunks_cap_run_length() {
+        let runs = ChunkSize::Days(7).split(&days("2024-03-01", "2024-03-16"));
+        assert_eq!(runs, [
+            (date("2024-03-01"), date("2024-03-07")),
+            (date("2024-03-08"), date("2024-03-14")),
+            (date("2024-03-15"), date("2024-03-16")),
+        ]);
+        assert!(ChunkSize::Days(1).split(&days("2024-03-01", "2024-03-03")).iter().all(|(first, last)| first == last));
+    }
+
+    #[test]
+    fn day_ranges_cover_dst_days() {
+        let window = ScrapeWindow::from_config(None).unwrap();
+
+        // Spring forward: the local day has 23 hours
+        let (start, end) = window.range_for_days(date("2024-03-31"), date("2024-03-31"));
+        assert_eq!(start, Utc.with_ymd_and_hms(2024, 3, 30, 23, 0, 0).unwrap());
+        assert_eq!(end, Utc.with_ymd_and_hms(2024, 3, 31, 22, 0, 0).unwrap());
+
+        // Fall back: the local day has 25 hours
+        let (start, end) = window.range_for_days(date("2024-10-27"), date("2024-10-27"));
+        assert_eq!(start, Utc.with_ymd_and_hms(2024, 10, 26, 22, 0, 0).unwrap());
+        assert_eq!(end, Utc.with_ymd_and_hms(2024, 10, 27, 23, 0, 0).unwrap());
+
+        let (start, end) = window.shifted(Duration::hours(1)).range_for_days(date("2024-03-31"), date("2024-04-01"));
+        assert_eq!(start, Utc.with_ymd_and_hms(2024, 3, 31, 0, 0, 0).unwrap());
+        assert_eq!(end, Utc.with_ymd_and_hms(2024, 4, 1, 23, 0, 0).unwrap());
+    }
+}